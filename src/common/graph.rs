@@ -0,0 +1,352 @@
+use std::ops::Index;
+
+use anyhow::{anyhow, Result};
+use bevy::math::Vec3;
+
+use super::track::Track;
+
+/// The type of a connection between two tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectorKind {
+    Stairs,
+    Elevator,
+}
+
+/// A point on one of the tracks of a graph.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub track: usize,
+    pub distance: f32,
+}
+
+/// Links a point on one track with a point on another track, connectors can be used in both
+/// directions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackConnector {
+    pub kind: ConnectorKind,
+    pub a: TrackPoint,
+    pub b: TrackPoint,
+}
+
+/// A connector as seen from one of its ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackConnection {
+    pub kind: ConnectorKind,
+    pub from: TrackPoint,
+    pub to: TrackPoint,
+}
+
+impl TrackConnector {
+    /// Returns the connection starting on the given track, if the connector has an end on it.
+    pub fn starting_on(&self, track: usize) -> Option<TrackConnection> {
+        let (from, to) = if self.a.track == track {
+            (self.a, self.b)
+        } else if self.b.track == track {
+            (self.b, self.a)
+        } else {
+            return None;
+        };
+        Some(TrackConnection {
+            kind: self.kind,
+            from,
+            to,
+        })
+    }
+}
+
+/// The tracks of all floors and the connectors between them.
+#[derive(Debug, Clone, Default)]
+pub struct TrackGraph {
+    tracks: Vec<Track>,
+    connectors: Vec<TrackConnector>,
+}
+
+impl TrackGraph {
+    pub fn new(tracks: Vec<Track>) -> Self {
+        Self {
+            tracks,
+            connectors: Vec::new(),
+        }
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    pub fn connectors(&self) -> &[TrackConnector] {
+        &self.connectors
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Connects two tracks at the points closest to the given position.
+    pub fn connect(
+        &mut self,
+        kind: ConnectorKind,
+        a: usize,
+        b: usize,
+        position: Vec3,
+    ) -> Result<()> {
+        let point_on = |track: usize| {
+            self.tracks
+                .get(track)
+                .map(|t| TrackPoint {
+                    track,
+                    distance: t.distance_of(position),
+                })
+                .ok_or(anyhow!("Connector references missing track {}", track))
+        };
+        let connector = TrackConnector {
+            kind,
+            a: point_on(a)?,
+            b: point_on(b)?,
+        };
+        self.connectors.push(connector);
+        Ok(())
+    }
+
+    /// Appends a copy of the other graph moved by `offset`, the tracks are stitched to the
+    /// tracks with the same index (floor) and the connectors are copied. Tracks missing in this
+    /// graph are added as they are.
+    pub fn append(&mut self, other: &TrackGraph, offset: Vec3) -> Result<()> {
+        for (index, track) in other.tracks.iter().enumerate() {
+            match self.tracks.get_mut(index) {
+                Some(existing) => existing
+                    .append(track, offset)
+                    .map_err(|error| anyhow!("Can't stitch track {}: {}", index, error))?,
+                None => {
+                    let points = track.points().iter().map(|point| *point + offset).collect();
+                    self.tracks.push(Track::new(points)?);
+                }
+            }
+        }
+        for connector in other.connectors.iter() {
+            let position = other.position_at(connector.a) + offset;
+            self.connect(
+                connector.kind,
+                connector.a.track,
+                connector.b.track,
+                position,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns the connections starting within `radius` of the point, closest first.
+    pub fn connections_near(&self, point: TrackPoint, radius: f32) -> Vec<TrackConnection> {
+        let mut connections: Vec<_> = self
+            .connectors
+            .iter()
+            .filter_map(|connector| connector.starting_on(point.track))
+            .filter(|connection| (connection.from.distance - point.distance).abs() <= radius)
+            .collect();
+        connections.sort_by(|a, b| {
+            let a = (a.from.distance - point.distance).abs();
+            let b = (b.from.distance - point.distance).abs();
+            a.total_cmp(&b)
+        });
+        connections
+    }
+
+    /// Removes the parts of the tracks left of `x` and returns the length removed from the start
+    /// of every track. Connectors with an end on a removed part are dropped, the others move
+    /// with their tracks.
+    pub fn trim_before(&mut self, x: f32) -> Vec<f32> {
+        let removed: Vec<f32> = self
+            .tracks
+            .iter_mut()
+            .map(|track| {
+                let first = track.first();
+                let distance = track.distance_of(Vec3::new(x, first.y, first.z));
+                track.trim_start(distance)
+            })
+            .collect();
+        self.connectors.retain(|connector| {
+            [connector.a, connector.b]
+                .iter()
+                .all(|end| end.distance >= removed[end.track])
+        });
+        for connector in self.connectors.iter_mut() {
+            connector.a.distance -= removed[connector.a.track];
+            connector.b.distance -= removed[connector.b.track];
+        }
+        removed
+    }
+
+    /// Returns the closest connection within `radius` of the point leading to the track
+    /// matching the predicate.
+    pub fn connection_near(
+        &self,
+        point: TrackPoint,
+        radius: f32,
+        predicate: impl Fn(&TrackConnection) -> bool,
+    ) -> Option<TrackConnection> {
+        self.connections_near(point, radius)
+            .into_iter()
+            .find(|connection| predicate(connection))
+    }
+
+    /// Returns the point on any track closest to the position, `None` for an empty graph.
+    pub fn closest_point(&self, position: Vec3) -> Option<TrackPoint> {
+        self.tracks
+            .iter()
+            .enumerate()
+            .map(|(track, t)| (track, t.closest_point(position)))
+            .min_by(|(_, a), (_, b)| {
+                let a = a.position.distance_squared(position);
+                let b = b.position.distance_squared(position);
+                a.total_cmp(&b)
+            })
+            .map(|(track, projection)| TrackPoint {
+                track,
+                distance: projection.distance,
+            })
+    }
+
+    /// Returns the world position of a point on the graph.
+    pub fn position_at(&self, point: TrackPoint) -> Vec3 {
+        self.tracks[point.track].position_at(point.distance)
+    }
+}
+
+impl Index<usize> for TrackGraph {
+    type Output = Track;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.tracks[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two floors 4 units apart, connected by stairs at x=2 and an elevator at x=8
+    fn graph() -> TrackGraph {
+        let floor = |y: f32| Track::new(vec![Vec3::new(0.0, y, 0.0), Vec3::new(10.0, y, 0.0)]);
+        let mut graph = TrackGraph::new(vec![floor(0.0).unwrap(), floor(4.0).unwrap()]);
+        graph
+            .connect(ConnectorKind::Stairs, 0, 1, Vec3::new(2.0, 1.0, 0.0))
+            .unwrap();
+        graph
+            .connect(ConnectorKind::Elevator, 1, 0, Vec3::new(8.0, 2.0, 0.0))
+            .unwrap();
+        graph
+    }
+
+    #[test]
+    fn connect_projects_onto_both_tracks() {
+        let graph = graph();
+        assert_eq!(
+            graph.connectors()[0],
+            TrackConnector {
+                kind: ConnectorKind::Stairs,
+                a: TrackPoint {
+                    track: 0,
+                    distance: 2.0
+                },
+                b: TrackPoint {
+                    track: 1,
+                    distance: 2.0
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn connect_missing_track_fails() {
+        let mut graph = graph();
+        assert!(graph
+            .connect(ConnectorKind::Stairs, 0, 5, Vec3::ZERO)
+            .is_err());
+        assert_eq!(graph.connectors().len(), 2);
+    }
+
+    #[test]
+    fn connections_near() {
+        let graph = graph();
+        let point = TrackPoint {
+            track: 0,
+            distance: 3.0,
+        };
+        let connections = graph.connections_near(point, 1.5);
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].kind, ConnectorKind::Stairs);
+        assert_eq!(connections[0].to.track, 1);
+
+        // connectors work in both directions and are sorted by distance
+        let connections = graph.connections_near(point, 10.0);
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections[1].kind, ConnectorKind::Elevator);
+        assert_eq!(connections[1].from.distance, 8.0);
+        assert_eq!(connections[1].to.track, 1);
+
+        assert!(graph.connections_near(point, 0.5).is_empty());
+    }
+
+    #[test]
+    fn append_stitches_floors_and_copies_connectors() {
+        let mut stitched = TrackGraph::default();
+        stitched.append(&graph(), Vec3::ZERO).unwrap();
+        stitched
+            .append(&graph(), Vec3::new(10.0, 0.0, 0.0))
+            .unwrap();
+        assert_eq!(stitched.len(), 2);
+        assert_eq!(stitched[0].length(), 20.0);
+        assert_eq!(stitched[1].last(), Vec3::new(20.0, 4.0, 0.0));
+        assert_eq!(stitched.connectors().len(), 4);
+        let elevator = &stitched.connectors()[3];
+        assert_eq!(elevator.kind, ConnectorKind::Elevator);
+        assert_eq!(elevator.a.distance, 18.0);
+        assert_eq!(elevator.b.distance, 18.0);
+    }
+
+    #[test]
+    fn trim_before_shifts_tracks_and_connectors() {
+        let mut trimmed = TrackGraph::default();
+        trimmed.append(&graph(), Vec3::ZERO).unwrap();
+        trimmed.append(&graph(), Vec3::new(10.0, 0.0, 0.0)).unwrap();
+        let connectors = trimmed.connectors().len();
+        assert_eq!(trimmed.trim_before(9.0), [9.0, 9.0]);
+        assert_eq!(trimmed[0].first(), Vec3::new(9.0, 0.0, 0.0));
+        assert_eq!(trimmed[1].length(), 11.0);
+        // the connectors of the first copy are left of the cut
+        assert_eq!(trimmed.connectors().len(), connectors / 2);
+        let elevator = &trimmed.connectors()[1];
+        assert_eq!(elevator.a.distance, 9.0);
+        assert_eq!(trimmed.position_at(elevator.b), Vec3::new(18.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn closest_point() {
+        let graph = graph();
+        assert_eq!(
+            graph.closest_point(Vec3::new(3.0, 3.5, 1.0)),
+            Some(TrackPoint {
+                track: 1,
+                distance: 3.0
+            })
+        );
+        assert_eq!(TrackGraph::default().closest_point(Vec3::ZERO), None);
+    }
+
+    #[test]
+    fn connection_near_with_predicate() {
+        let graph = graph();
+        let point = TrackPoint {
+            track: 1,
+            distance: 5.0,
+        };
+        let elevator = graph
+            .connection_near(point, 5.0, |c| c.kind == ConnectorKind::Elevator)
+            .unwrap();
+        assert_eq!(elevator.to.track, 0);
+        assert_eq!(graph.position_at(elevator.to), Vec3::new(8.0, 0.0, 0.0));
+    }
+}
@@ -1,41 +1,249 @@
+use std::time::Duration;
+
 use anyhow::Result;
-use bevy::asset::{RecursiveDependencyLoadState, UntypedAssetId};
-use bevy::log::info;
+use bevy::asset::{LoadState, RecursiveDependencyLoadState, UntypedAssetId};
+use bevy::log::{info, warn};
 use bevy::prelude::*;
-use bevy::utils::HashSet;
+use bevy::utils::{HashMap, HashSet, Instant};
+
+/// Loading state of a single asset tracked by the loader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetLoadingState {
+    /// Submitted but the asset server didn't start loading yet
+    Queued,
+    /// The asset or one of its dependencies is loading
+    Loading,
+    Loaded,
+    /// Failed with the reason
+    Failed(String),
+}
+
+impl AssetLoadingState {
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Self::Queued | Self::Loading)
+    }
+}
+
+/// Number of tracked assets per loading state, sent as event whenever it changes.
+#[derive(Event, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AssetLoadingProgress {
+    pub queued: usize,
+    pub loading: usize,
+    pub loaded: usize,
+    pub failed: usize,
+}
+
+impl AssetLoadingProgress {
+    pub fn total(&self) -> usize {
+        self.queued + self.loading + self.loaded + self.failed
+    }
+
+    /// Fraction of loaded assets, between 0 and 1
+    pub fn fraction(&self) -> f32 {
+        if self.total() == 0 {
+            1.0
+        } else {
+            self.loaded as f32 / self.total() as f32
+        }
+    }
+}
+
+/// Retried assets not seen reloading after this long fail with their current load state, the
+/// server sends no event for a reload failing again.
+const RETRY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Assets pending for longer than the timeouts are reported as failed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AssetLoaderTimeouts {
+    /// Default timeout of every asset, counted from when the asset was added
+    pub asset: Option<Duration>,
+    /// Timeout for all assets, counted from when the first asset was added
+    pub global: Option<Duration>,
+}
 
+/// Resubmitted asset, until it is seen reloading its load state is the one before the reload.
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    started: Instant,
+    reloading: bool,
+}
+
+/// Loads assets by name and tracks their loading state.
 #[derive(Resource)]
 pub struct AssetLoader {
-    pending: HashSet<UntypedAssetId>,
+    handles: HashMap<String, UntypedHandle>,
+    optional: HashSet<UntypedAssetId>,
+    assets: HashMap<UntypedAssetId, AssetLoadingState>,
+    retrying: HashMap<UntypedAssetId, Retry>,
+    reported_progress: Option<AssetLoadingProgress>,
+    timeouts: AssetLoaderTimeouts,
+    asset_timeouts: HashMap<UntypedAssetId, Duration>,
+    added: HashMap<UntypedAssetId, Instant>,
+    started: Option<Instant>,
 }
 
 impl AssetLoader {
     pub fn new() -> Self {
         Self {
-            pending: HashSet::default(),
+            handles: HashMap::default(),
+            optional: HashSet::default(),
+            assets: HashMap::default(),
+            retrying: HashMap::default(),
+            reported_progress: None,
+            timeouts: AssetLoaderTimeouts::default(),
+            asset_timeouts: HashMap::default(),
+            added: HashMap::default(),
+            started: None,
+        }
+    }
+
+    pub fn set_timeouts(&mut self, timeouts: AssetLoaderTimeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Tracks the loading asset by name, failing optional assets don't fail loading.
+    pub fn add(&mut self, key: &str, handle: UntypedHandle, required: bool) {
+        let id = handle.id();
+        if !required {
+            self.optional.insert(id);
+        }
+        self.handles.insert(key.to_string(), handle);
+        self.assets.insert(id, AssetLoadingState::Queued);
+        self.added.insert(id, Instant::now());
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    /// Overrides the default timeout for the named asset.
+    pub fn set_asset_timeout(&mut self, key: &str, timeout: Duration) {
+        if let Some(handle) = self.handles.get(key) {
+            self.asset_timeouts.insert(handle.id(), timeout);
         }
     }
 
-    pub fn update_loading_state(&mut self, server: &AssetServer) -> Result<()> {
+    /// Returns the timeout that elapsed at `now` for the pending asset, if any.
+    fn elapsed_timeout(&self, id: &UntypedAssetId, now: Instant) -> Option<Duration> {
+        let elapsed = |since: &Instant| now.saturating_duration_since(*since);
+        let asset_timeout = self
+            .asset_timeouts
+            .get(id)
+            .copied()
+            .or(self.timeouts.asset)
+            .filter(|timeout| self.added.get(id).is_some_and(|t| elapsed(t) >= *timeout));
+        let global_timeout = self
+            .timeouts
+            .global
+            .filter(|timeout| self.started.is_some_and(|t| elapsed(&t) >= *timeout));
+        asset_timeout.or(global_timeout)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.handles.contains_key(key)
+    }
+
+    /// Returns the handle of the named asset.
+    pub fn get<A: Asset>(&self, key: &str) -> Option<Handle<A>> {
+        self.handles
+            .get(key)
+            .map(|handle| handle.clone().typed::<A>())
+    }
+
+    /// Returns true if the named asset is loaded.
+    pub fn is_loaded(&self, key: &str) -> bool {
+        self.handles
+            .get(key)
+            .and_then(|handle| self.assets.get(&handle.id()))
+            .is_some_and(|state| *state == AssetLoadingState::Loaded)
+    }
+
+    /// Updates the state of all pending assets, `dependencies` returns the dependencies of an
+    /// asset to report which one is blocking an asset that timed out.
+    pub fn update_loading_state(
+        &mut self,
+        server: &AssetServer,
+        dependencies: impl Fn(UntypedAssetId) -> Vec<UntypedAssetId>,
+    ) -> Result<()> {
+        self.update_loading_state_at(Instant::now(), server, dependencies)
+    }
+
+    /// Updates the state of all pending assets like [`Self::update_loading_state`], with the
+    /// timeouts measured up to `now`.
+    pub fn update_loading_state_at(
+        &mut self,
+        now: Instant,
+        server: &AssetServer,
+        dependencies: impl Fn(UntypedAssetId) -> Vec<UntypedAssetId>,
+    ) -> Result<()> {
         let mut errors = Vec::new();
+        let timed_out: Vec<_> = self
+            .assets
+            .iter()
+            .filter(|(_, state)| state.is_pending())
+            .filter_map(|(id, _)| Some((*id, self.elapsed_timeout(id, now)?)))
+            .collect();
 
-        self.pending.retain(|pending| {
-            let path = server.get_path(*pending);
-            let states = server.get_load_states(*pending);
-            states.map_or(true, |(_, _, state)| {
-                if state == RecursiveDependencyLoadState::Loaded {
+        for (id, state) in self.assets.iter_mut() {
+            if !state.is_pending() {
+                continue;
+            }
+            let server_state = server.get_load_states(*id).map(|(_, _, state)| state);
+            // the reload starts in the background, until the server reports it loading or the
+            // asset events report it loaded the server state is the one before the reload
+            if let Some(retry) = self.retrying.get_mut(id) {
+                retry.reloading |= server_state == Some(RecursiveDependencyLoadState::Loading);
+                if !retry.reloading && now.saturating_duration_since(retry.started) < RETRY_TIMEOUT
+                {
+                    continue;
+                }
+            }
+            let retried = self.retrying.contains_key(id);
+            let path = server.get_path(*id);
+            *state = match server_state {
+                Some(RecursiveDependencyLoadState::Loaded) => {
                     info!("Successfully loaded asset: {:?}", path);
-                    false
-                } else if state == RecursiveDependencyLoadState::Failed {
+                    AssetLoadingState::Loaded
+                }
+                Some(RecursiveDependencyLoadState::Failed) if self.optional.contains(id) => {
+                    warn!("Failed loading optional asset: {:?}", path);
+                    AssetLoadingState::Failed("failed loading optional asset".to_string())
+                }
+                Some(RecursiveDependencyLoadState::Failed) if retried => {
+                    errors.push(format!("Retry failed loading asset: {:?}", path));
+                    AssetLoadingState::Failed("retry failed".to_string())
+                }
+                Some(RecursiveDependencyLoadState::Failed) => {
                     errors.push(format!("Failed loading asset: {:?}", path));
-                    false
-                } else {
-                    true
+                    AssetLoadingState::Failed("failed loading asset".to_string())
                 }
-            })
-        });
+                Some(RecursiveDependencyLoadState::Loading) => AssetLoadingState::Loading,
+                Some(RecursiveDependencyLoadState::NotLoaded) | None => AssetLoadingState::Queued,
+            };
+        }
 
-        if errors.is_empty() {
+        for (id, timeout) in timed_out {
+            if !self.assets[&id].is_pending() {
+                continue;
+            }
+            let path = server.get_path(id);
+            let reason = match blocking_dependency(server, id, &dependencies) {
+                Some(blocking) => format!(
+                    "timed out after {:.0}s waiting for {}",
+                    timeout.as_secs_f32(),
+                    blocking
+                ),
+                None => format!("timed out after {:.0}s", timeout.as_secs_f32()),
+            };
+            if self.optional.contains(&id) {
+                warn!("Optional asset {:?} {}", path, reason);
+            } else {
+                errors.push(format!("Asset {:?} {}", path, reason));
+            }
+            self.assets.insert(id, AssetLoadingState::Failed(reason));
+        }
+        let assets = &self.assets;
+        self.retrying
+            .retain(|id, _| assets.get(id).is_some_and(AssetLoadingState::is_pending));
+
+        if errors.is_empty() && self.required_failures() == 0 {
             Ok(())
         } else {
             Err(anyhow::anyhow!(
@@ -45,11 +253,356 @@ impl AssetLoader {
         }
     }
 
-    pub fn add_pending(&mut self, id: UntypedAssetId) {
-        self.pending.insert(id);
+    fn required_failures(&self) -> usize {
+        self.assets
+            .iter()
+            .filter(|(id, state)| {
+                matches!(state, AssetLoadingState::Failed(_)) && !self.optional.contains(*id)
+            })
+            .count()
+    }
+
+    /// Reports a loaded asset as failed, for assets that loaded but are unusable.
+    pub fn add_failed(&mut self, key: &str, reason: String) {
+        if let Some(handle) = self.handles.get(key) {
+            self.assets
+                .insert(handle.id(), AssetLoadingState::Failed(reason));
+        }
     }
 
     pub fn is_finished(&self) -> bool {
-        self.pending.is_empty()
+        !self.assets.values().any(AssetLoadingState::is_pending)
+    }
+
+    pub fn progress(&self) -> AssetLoadingProgress {
+        let mut progress = AssetLoadingProgress::default();
+        for state in self.assets.values() {
+            match state {
+                AssetLoadingState::Queued => progress.queued += 1,
+                AssetLoadingState::Loading => progress.loading += 1,
+                AssetLoadingState::Loaded => progress.loaded += 1,
+                AssetLoadingState::Failed(_) => progress.failed += 1,
+            }
+        }
+        progress
+    }
+
+    /// Returns the progress if it changed since the last call.
+    pub fn progress_changed(&mut self) -> Option<AssetLoadingProgress> {
+        let progress = self.progress();
+        if self.reported_progress == Some(progress) {
+            return None;
+        }
+        self.reported_progress = Some(progress);
+        Some(progress)
+    }
+
+    /// Returns the path and loading state of every tracked asset, sorted by path.
+    pub fn states(&self, server: &AssetServer) -> Vec<(String, AssetLoadingState)> {
+        let mut states: Vec<_> = self
+            .assets
+            .iter()
+            .map(|(id, state)| {
+                let path = server
+                    .get_path(*id)
+                    .map_or_else(|| format!("{:?}", id), |path| path.to_string());
+                (path, state.clone())
+            })
+            .collect();
+        states.sort_by(|(a, _), (b, _)| a.cmp(b));
+        states
+    }
+
+    /// Returns the paths of the failed assets together with the reason they failed.
+    pub fn failures(&self, server: &AssetServer) -> Vec<(String, String)> {
+        self.states(server)
+            .into_iter()
+            .filter_map(|(path, state)| match state {
+                AssetLoadingState::Failed(reason) => Some((path, reason)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Notes that the asset server finished (re)loading the asset, its load state is current.
+    pub fn asset_reloaded(&mut self, id: UntypedAssetId) {
+        if let Some(retry) = self.retrying.get_mut(&id) {
+            retry.reloading = true;
+        }
+    }
+
+    /// Reloads all failed assets and queues them again. The server reports no event for a
+    /// reload failing again before it was seen loading, those assets fail after a short
+    /// retry deadline.
+    pub fn retry_failed(&mut self, server: &AssetServer) {
+        for (id, state) in self.assets.iter_mut() {
+            if let AssetLoadingState::Failed(_) = state {
+                if let Some(path) = server.get_path(*id) {
+                    info!("Retry loading asset: {:?}", path);
+                    server.reload(path);
+                    let retry = Retry {
+                        started: Instant::now(),
+                        reloading: false,
+                    };
+                    self.retrying.insert(*id, retry);
+                    self.added.insert(*id, Instant::now());
+                    self.started = Some(Instant::now());
+                    *state = AssetLoadingState::Queued;
+                }
+            }
+        }
+    }
+}
+
+/// Forwards the asset events of reloaded assets to the loader, see
+/// [`AssetLoader::retry_failed`].
+pub fn asset_event_system<A: Asset>(
+    mut events: EventReader<AssetEvent<A>>,
+    loader: Option<ResMut<AssetLoader>>,
+) {
+    let Some(mut loader) = loader else {
+        events.clear();
+        return;
+    };
+    for event in events.read() {
+        if let AssetEvent::Modified { id } | AssetEvent::LoadedWithDependencies { id } = event {
+            loader.asset_reloaded(id.untyped());
+        }
+    }
+}
+
+/// Returns the path of the first unloaded dependency of the asset, or the asset itself while it
+/// didn't finish loading.
+fn blocking_dependency(
+    server: &AssetServer,
+    id: UntypedAssetId,
+    dependencies: &impl Fn(UntypedAssetId) -> Vec<UntypedAssetId>,
+) -> Option<String> {
+    let describe = |id: UntypedAssetId| {
+        let path = server
+            .get_path(id)
+            .map_or_else(|| format!("{:?}", id), |path| path.to_string());
+        let state = server.get_load_states(id).map(|(state, _, _)| state);
+        format!("{} ({:?})", path, state)
+    };
+    match server.get_load_states(id) {
+        Some((LoadState::Loaded, _, _)) => dependencies(id)
+            .into_iter()
+            .find(|dependency| {
+                server.get_recursive_dependency_load_state(*dependency)
+                    != Some(RecursiveDependencyLoadState::Loaded)
+            })
+            .map(describe),
+        Some(_) => Some(describe(id)),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bevy::asset::io::memory::Dir;
+
+    use super::*;
+    use crate::common::manifest::AssetManifest;
+    use crate::common::ron_asset::RonAssetLoader;
+    use crate::common::testing::memory_asset_app;
+
+    const PATH: &str = "test.manifest.ron";
+
+    /// An app loading manifests from the directory, with a loader but without its systems
+    fn loader_app(dir: &Dir) -> App {
+        let mut app = memory_asset_app(dir);
+        app.init_asset::<AssetManifest>();
+        app.register_asset_loader(RonAssetLoader::<AssetManifest>::new(&["manifest.ron"]));
+        app.add_systems(PreUpdate, asset_event_system::<AssetManifest>);
+        app.insert_resource(AssetLoader::new());
+        app
+    }
+
+    /// Updates the app and the loader, returns the result of the last loader update
+    fn update(app: &mut App) -> Result<()> {
+        app.update();
+        let server = app.world.resource::<AssetServer>().clone();
+        app.world
+            .resource_mut::<AssetLoader>()
+            .update_loading_state(&server, |_| Vec::new())
+    }
+
+    /// Updates until nothing is pending, returns the result of the last loader update
+    fn update_until_finished(app: &mut App) -> Result<()> {
+        for _ in 0..1000 {
+            let result = update(app);
+            if app.world.resource::<AssetLoader>().is_finished() {
+                return result;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        panic!("loading didn't finish");
+    }
+
+    /// Adds an asset the server doesn't know, it stays queued until it times out
+    fn add_unknown(loader: &mut AssetLoader, key: &str, index: u128, required: bool) {
+        let handle = Handle::<AssetManifest>::weak_from_u128(index).untyped();
+        loader.add(key, handle, required);
+    }
+
+    fn state(loader: &AssetLoader, key: &str) -> AssetLoadingState {
+        let id = loader.handles[key].id();
+        loader.assets[&id].clone()
+    }
+
+    #[test]
+    fn assets_time_out() {
+        let app = loader_app(&Dir::default());
+        let server = app.world.resource::<AssetServer>().clone();
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut loader = AssetLoader::new();
+        loader.set_timeouts(AssetLoaderTimeouts {
+            asset: Some(Duration::from_secs(5)),
+            global: None,
+        });
+        add_unknown(&mut loader, "required", 1, true);
+        add_unknown(&mut loader, "optional", 2, false);
+        assert!(loader
+            .update_loading_state_at(at(4), &server, |_| Vec::new())
+            .is_ok());
+        assert_eq!(state(&loader, "required"), AssetLoadingState::Queued);
+
+        let result = loader.update_loading_state_at(at(6), &server, |_| Vec::new());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("timed out after 5s"));
+        let reason = "timed out after 5s".to_string();
+        assert_eq!(
+            state(&loader, "required"),
+            AssetLoadingState::Failed(reason.clone())
+        );
+        assert_eq!(
+            state(&loader, "optional"),
+            AssetLoadingState::Failed(reason)
+        );
+        assert!(loader.is_finished());
+    }
+
+    #[test]
+    fn asset_timeouts_override_the_default() {
+        let app = loader_app(&Dir::default());
+        let server = app.world.resource::<AssetServer>().clone();
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut loader = AssetLoader::new();
+        loader.set_timeouts(AssetLoaderTimeouts {
+            asset: Some(Duration::from_secs(5)),
+            global: None,
+        });
+        add_unknown(&mut loader, "manifest", 1, true);
+        loader.set_asset_timeout("manifest", Duration::from_secs(30));
+        add_unknown(&mut loader, "level", 2, false);
+        loader.set_asset_timeout("level", Duration::from_secs(2));
+
+        assert!(loader
+            .update_loading_state_at(at(3), &server, |_| Vec::new())
+            .is_ok());
+        assert!(matches!(
+            state(&loader, "level"),
+            AssetLoadingState::Failed(_)
+        ));
+        assert!(loader
+            .update_loading_state_at(at(10), &server, |_| Vec::new())
+            .is_ok());
+        assert_eq!(state(&loader, "manifest"), AssetLoadingState::Queued);
+        assert!(loader
+            .update_loading_state_at(at(31), &server, |_| Vec::new())
+            .is_err());
+        assert!(loader.is_finished());
+    }
+
+    #[test]
+    fn loading_times_out_globally() {
+        let app = loader_app(&Dir::default());
+        let server = app.world.resource::<AssetServer>().clone();
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut loader = AssetLoader::new();
+        loader.set_timeouts(AssetLoaderTimeouts {
+            asset: None,
+            global: Some(Duration::from_secs(20)),
+        });
+        add_unknown(&mut loader, "first", 1, true);
+        assert!(loader
+            .update_loading_state_at(at(15), &server, |_| Vec::new())
+            .is_ok());
+        // fails every asset still pending, whatever its own timeout
+        add_unknown(&mut loader, "second", 2, true);
+        loader.set_asset_timeout("second", Duration::from_secs(60));
+        let result = loader.update_loading_state_at(at(21), &server, |_| Vec::new());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("timed out after 20s"));
+        assert_eq!(loader.progress().failed, 2);
+    }
+
+    #[test]
+    fn retried_assets_wait_for_the_reload() {
+        let dir = Dir::default();
+        dir.insert_asset_text(Path::new(PATH), "(assets: {");
+        let mut app = loader_app(&dir);
+        let handle = app
+            .world
+            .resource::<AssetServer>()
+            .load::<AssetManifest>(PATH);
+        app.world
+            .resource_mut::<AssetLoader>()
+            .add("manifest", handle.untyped(), true);
+        assert!(update_until_finished(&mut app).is_err());
+        assert_eq!(app.world.resource::<AssetLoader>().progress().failed, 1);
+
+        dir.insert_asset_text(Path::new(PATH), "(assets: {})");
+        let server = app.world.resource::<AssetServer>().clone();
+        app.world
+            .resource_mut::<AssetLoader>()
+            .retry_failed(&server);
+        // the server might still report the failure from before the reload
+        assert!(update(&mut app).is_ok());
+        assert_eq!(app.world.resource::<AssetLoader>().progress().failed, 0);
+        assert!(update_until_finished(&mut app).is_ok());
+        assert!(app.world.resource::<AssetLoader>().is_loaded("manifest"));
+    }
+
+    #[test]
+    fn retried_assets_fail_after_the_retry_deadline() {
+        let dir = Dir::default();
+        dir.insert_asset_text(Path::new(PATH), "(assets: {");
+        let mut app = loader_app(&dir);
+        let handle = app
+            .world
+            .resource::<AssetServer>()
+            .load::<AssetManifest>(PATH);
+        app.world
+            .resource_mut::<AssetLoader>()
+            .add("manifest", handle.untyped(), true);
+        assert!(update_until_finished(&mut app).is_err());
+
+        let server = app.world.resource::<AssetServer>().clone();
+        app.world
+            .resource_mut::<AssetLoader>()
+            .retry_failed(&server);
+        for _ in 0..10 {
+            app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let mut loader = app.world.resource_mut::<AssetLoader>();
+        let result =
+            loader.update_loading_state_at(Instant::now() + RETRY_TIMEOUT, &server, |_| Vec::new());
+        assert!(result.is_err());
+        assert_eq!(
+            state(&loader, "manifest"),
+            AssetLoadingState::Failed("retry failed".to_string())
+        );
     }
 }
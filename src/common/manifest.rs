@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use serde::Deserialize;
+
+/// The type of asset a manifest entry is loaded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ManifestAssetKind {
+    Gltf,
+    Image,
+    /// A building layout, see `BuildingLevel`
+    Level,
+}
+
+/// A named asset declared in the manifest.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub kind: ManifestAssetKind,
+    /// Failing to load a required asset fails loading, optional assets only log a warning
+    #[serde(default = "ManifestEntry::default_required")]
+    pub required: bool,
+    /// Seconds after which the asset fails if it didn't load, overrides `asset_timeout`
+    #[serde(default)]
+    pub timeout: Option<f32>,
+}
+
+impl ManifestEntry {
+    fn default_required() -> bool {
+        true
+    }
+}
+
+/// Declares the assets of the game by name, loaded from `*.manifest.ron` files.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct AssetManifest {
+    /// Default timeout in seconds for every asset
+    #[serde(default)]
+    pub asset_timeout: Option<f32>,
+    /// Timeout in seconds for loading all assets
+    #[serde(default)]
+    pub loading_timeout: Option<f32>,
+    pub assets: BTreeMap<String, ManifestEntry>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_game_manifest() {
+        let manifest: AssetManifest =
+            ron::de::from_str(include_str!("../../assets/game.manifest.ron")).unwrap();
+        let building = &manifest.assets["building"];
+        assert_eq!(building.path, "building.gltf");
+        assert_eq!(building.kind, ManifestAssetKind::Gltf);
+        assert!(building.required);
+        assert!(manifest.asset_timeout.is_some());
+    }
+
+    #[test]
+    fn parse_optional_entry() {
+        let manifest: AssetManifest = ron::de::from_str(
+            r#"(assets: { "icon": (path: "icon.png", kind: Image, required: false, timeout: Some(2.5)) })"#,
+        )
+        .unwrap();
+        assert!(!manifest.assets["icon"].required);
+        assert_eq!(manifest.assets["icon"].timeout, Some(2.5));
+        assert_eq!(manifest.loading_timeout, None);
+    }
+}
@@ -1,2 +1,8 @@
+pub mod graph;
 pub mod loader;
+pub mod manifest;
+pub mod random;
+pub mod ron_asset;
+#[cfg(test)]
+pub mod testing;
 pub mod track;
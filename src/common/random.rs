@@ -0,0 +1,118 @@
+use std::ops::RangeInclusive;
+
+/// A small seeded random number generator (SplitMix64).
+///
+/// The sequence only depends on the seed, it is the same on every platform and for every
+/// build, so anything generated from a seed can be reproduced exactly.
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`, `n` needs to be greater than zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        // rejects the values that would make the modulo biased
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % n;
+            }
+        }
+    }
+
+    /// Returns a number in the inclusive range.
+    pub fn range(&mut self, range: RangeInclusive<usize>) -> usize {
+        let (start, end) = range.into_inner();
+        if end <= start {
+            return start;
+        }
+        start + self.below((end - start) as u64 + 1) as usize
+    }
+
+    /// Returns a number in `0.0..1.0`.
+    pub fn unit(&mut self) -> f32 {
+        // the top 24 bits fit the mantissa exactly
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Picks an index with a probability proportional to its weight, returns `None` if all
+    /// weights are zero.
+    pub fn weighted(&mut self, weights: &[u32]) -> Option<usize> {
+        let total: u64 = weights.iter().map(|weight| *weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = self.below(total);
+        weights.iter().position(|weight| {
+            let weight = *weight as u64;
+            if pick < weight {
+                true
+            } else {
+                pick -= weight;
+                false
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_sequence() {
+        let mut random = SeededRandom::new(0);
+        assert_eq!(random.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(random.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = SeededRandom::new(42);
+        let mut b = SeededRandom::new(42);
+        assert!((0..100).all(|_| a.next_u64() == b.next_u64()));
+    }
+
+    #[test]
+    fn range_is_inclusive() {
+        let mut random = SeededRandom::new(7);
+        let values: Vec<_> = (0..200).map(|_| random.range(2..=4)).collect();
+        assert!(values.iter().all(|value| (2..=4).contains(value)));
+        assert!(values.contains(&2) && values.contains(&4));
+        assert_eq!(random.range(3..=3), 3);
+    }
+
+    #[test]
+    fn unit_is_below_one() {
+        let mut random = SeededRandom::new(3);
+        let values: Vec<_> = (0..200).map(|_| random.unit()).collect();
+        assert!(values.iter().all(|value| (0.0..1.0).contains(value)));
+        assert!(
+            values.iter().any(|value| *value < 0.5) && values.iter().any(|value| *value >= 0.5)
+        );
+    }
+
+    #[test]
+    fn weighted_skips_zero_weights() {
+        let mut random = SeededRandom::new(1);
+        assert!((0..100).all(|_| random
+            .weighted(&[0, 3, 0, 1])
+            .is_some_and(|i| i == 1 || i == 3)));
+        assert_eq!(random.weighted(&[0, 0]), None);
+        assert_eq!(random.weighted(&[]), None);
+    }
+}
@@ -0,0 +1,17 @@
+//! Fixtures for headless `App` tests loading assets.
+
+use bevy::asset::io::memory::{Dir, MemoryAssetReader};
+use bevy::asset::io::{AssetSource, AssetSourceId};
+use bevy::prelude::*;
+
+/// An app with the minimal plugins whose asset server loads from the in-memory directory.
+pub fn memory_asset_app(dir: &Dir) -> App {
+    let dir = dir.clone();
+    let mut app = App::new();
+    app.register_asset_source(
+        AssetSourceId::Default,
+        AssetSource::build().with_reader(move || Box::new(MemoryAssetReader { root: dir.clone() })),
+    );
+    app.add_plugins((MinimalPlugins, AssetPlugin::default()));
+    app
+}
@@ -1,13 +1,100 @@
 use std::cmp::Ordering;
 
 use anyhow::{anyhow, Result};
-use bevy::{math::Vec3, render::mesh::Mesh};
+use bevy::{
+    math::Vec3,
+    render::{mesh::Mesh, render_resource::PrimitiveTopology},
+};
 
-#[derive(Debug)]
-pub struct Track(Vec<Vec3>);
+/// A polyline the characters can walk along, parameterized by arc length.
+///
+/// Positions on the track are addressed by their distance from the first point, the
+/// distance of every point is precomputed so lookups only need a binary search.
+#[derive(Debug, Clone)]
+pub struct Track {
+    points: Vec<Vec3>,
+    distances: Vec<f32>,
+}
+
+/// Largest distance between the end of a track and the start of a track appended to it
+const STITCH_DISTANCE: f32 = 0.05;
+
+/// How the points of a track are extracted from a mesh.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrackMode {
+    /// Flattens all vertices to the lowest height and orders them along the x axis.
+    #[default]
+    Flat,
+    /// Keeps the height of every vertex and orders them by following the edges of a line
+    /// mesh, the edges need to form a single unbranched path.
+    Connected,
+}
+
+impl TrackMode {
+    /// Line meshes are connected tracks, everything else is flattened.
+    pub fn for_mesh(mesh: &Mesh) -> Self {
+        match mesh.primitive_topology() {
+            PrimitiveTopology::LineList | PrimitiveTopology::LineStrip => Self::Connected,
+            _ => Self::Flat,
+        }
+    }
+
+    /// Returns the height difference of the vertices if extracting the track flattens them,
+    /// tracks with vertices at different heights need to be exported as line meshes.
+    pub fn lost_height(self, mesh: &Mesh) -> Option<f32> {
+        if self == Self::Connected {
+            return None;
+        }
+        let heights = mesh
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+            .and_then(|positions| positions.as_float3())
+            .into_iter()
+            .flatten()
+            .map(|point| point[1]);
+        let (min, max) = heights.fold((f32::MAX, f32::MIN), |(min, max), y| {
+            (min.min(y), max.max(y))
+        });
+        (max - min > FLAT_HEIGHT).then_some(max - min)
+    }
+}
+
+/// Largest height difference between the vertices of a flat track
+const FLAT_HEIGHT: f32 = 0.05;
+
+/// The result of projecting a point onto a track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackProjection {
+    /// Index of the segment between `points[segment]` and `points[segment + 1]`
+    pub segment: usize,
+    /// Distance along the track of the projected point
+    pub distance: f32,
+    /// The projected point on the track
+    pub position: Vec3,
+}
 
 impl Track {
+    /// Creates a track from an ordered list of points.
+    pub fn new(points: Vec<Vec3>) -> Result<Self> {
+        if points.is_empty() {
+            return Err(anyhow!("Track has no points!"));
+        }
+        let mut distances = Vec::with_capacity(points.len());
+        let mut distance = 0.0;
+        distances.push(distance);
+        for segment in points.windows(2) {
+            distance += segment[0].distance(segment[1]);
+            distances.push(distance);
+        }
+        Ok(Self { points, distances })
+    }
+
+    /// Creates a flat track from the vertices of a mesh, see [`TrackMode`].
+    #[cfg(test)]
     pub fn from_mesh(mesh: &Mesh) -> Result<Self> {
+        Self::from_mesh_with_mode(mesh, TrackMode::Flat)
+    }
+
+    pub fn from_mesh_with_mode(mesh: &Mesh, mode: TrackMode) -> Result<Self> {
         let points: Vec<Vec3> = mesh
             .attribute(Mesh::ATTRIBUTE_POSITION)
             .ok_or(anyhow!("Navmesh has no position!"))?
@@ -16,21 +103,560 @@ impl Track {
             .iter()
             .map(|point| Vec3::from_array(*point))
             .collect();
-        let min_y = points
-            .iter()
-            .map(|point| point.y)
-            .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Less))
-            .unwrap();
-        let mut points: Vec<Vec3> = points
-            .into_iter()
-            .map(|point| Vec3::new(point.x, min_y, point.z))
-            .collect();
-        points.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap());
-        points.dedup();
-        Ok(Self(points))
+        match mode {
+            TrackMode::Flat => Self::new(flat_points(points)?),
+            TrackMode::Connected => Self::new(connected_points(mesh, points)?),
+        }
+    }
+
+    /// Appends the points of another track moved by `offset`, the other track needs to start
+    /// where this track ends. The shared point is kept once, so the distances of existing
+    /// points don't change.
+    pub fn append(&mut self, other: &Track, offset: Vec3) -> Result<()> {
+        let start = other.first() + offset;
+        if self.last().distance(start) > STITCH_DISTANCE {
+            return Err(anyhow!(
+                "Track ends at {} but the appended track starts at {}!",
+                self.last(),
+                start
+            ));
+        }
+        for point in other.points.iter().skip(1).map(|point| *point + offset) {
+            let distance = self.length() + self.last().distance(point);
+            self.points.push(point);
+            self.distances.push(distance);
+        }
+        Ok(())
+    }
+
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
     }
 
     pub fn first(&self) -> Vec3 {
-        *self.0.first().expect("empty track error")
+        *self.points.first().expect("empty track error")
+    }
+
+    pub fn last(&self) -> Vec3 {
+        *self.points.last().expect("empty track error")
+    }
+
+    /// Total length of the track.
+    pub fn length(&self) -> f32 {
+        *self.distances.last().expect("empty track error")
+    }
+
+    /// Clamps the distance to the start and end of the track.
+    pub fn clamp(&self, distance: f32) -> f32 {
+        distance.clamp(0.0, self.length())
+    }
+
+    /// Returns the number of segments, a single point track has no segments.
+    pub fn segment_count(&self) -> usize {
+        self.points.len() - 1
+    }
+
+    /// Returns the index of the segment at the given distance (clamped to the track).
+    pub fn segment_at(&self, distance: f32) -> usize {
+        let index = self.distances.partition_point(|d| *d <= distance);
+        index
+            .saturating_sub(1)
+            .min(self.segment_count().saturating_sub(1))
+    }
+
+    /// Returns the point at the given distance along the track (clamped to the track).
+    pub fn position_at(&self, distance: f32) -> Vec3 {
+        if self.segment_count() == 0 {
+            return self.first();
+        }
+        let distance = self.clamp(distance);
+        let segment = self.segment_at(distance);
+        let (start, end) = (self.points[segment], self.points[segment + 1]);
+        let segment_length = self.distances[segment + 1] - self.distances[segment];
+        if segment_length <= f32::EPSILON {
+            return start;
+        }
+        start.lerp(end, (distance - self.distances[segment]) / segment_length)
+    }
+
+    /// Returns the normalized direction of the track at the given distance, pointing towards
+    /// increasing distances.
+    pub fn tangent_at(&self, distance: f32) -> Vec3 {
+        if self.segment_count() == 0 {
+            return Vec3::X;
+        }
+        let segment = self.segment_at(self.clamp(distance));
+        (self.points[segment + 1] - self.points[segment])
+            .try_normalize()
+            .unwrap_or(Vec3::X)
+    }
+
+    /// Projects the point onto the closest point of the track.
+    pub fn closest_point(&self, point: Vec3) -> TrackProjection {
+        if self.segment_count() == 0 {
+            return TrackProjection {
+                segment: 0,
+                distance: 0.0,
+                position: self.first(),
+            };
+        }
+        self.points
+            .windows(2)
+            .enumerate()
+            .map(|(segment, points)| {
+                let (start, end) = (points[0], points[1]);
+                let along = end - start;
+                let t = if along.length_squared() <= f32::EPSILON {
+                    0.0
+                } else {
+                    ((point - start).dot(along) / along.length_squared()).clamp(0.0, 1.0)
+                };
+                TrackProjection {
+                    segment,
+                    distance: self.distances[segment] + along.length() * t,
+                    position: start + along * t,
+                }
+            })
+            .min_by(|a, b| {
+                a.position
+                    .distance_squared(point)
+                    .partial_cmp(&b.position.distance_squared(point))
+                    .unwrap_or(Ordering::Equal)
+            })
+            .unwrap()
+    }
+
+    /// Returns the distance along the track of the closest point on the track.
+    pub fn distance_of(&self, point: Vec3) -> f32 {
+        self.closest_point(point).distance
+    }
+
+    /// Removes the part of the track before the distance and returns the removed length, the
+    /// distances of the remaining points shift back by it.
+    pub fn trim_start(&mut self, distance: f32) -> f32 {
+        let distance = self.clamp(distance);
+        if distance <= 0.0 {
+            return 0.0;
+        }
+        let segment = self.segment_at(distance);
+        let start = self.position_at(distance);
+        let mut points = vec![start];
+        points.extend(
+            self.points[segment + 1..]
+                .iter()
+                .filter(|point| **point != start),
+        );
+        *self = Self::new(points).expect("trimmed track keeps a point");
+        distance
+    }
+}
+
+fn flat_points(points: Vec<Vec3>) -> Result<Vec<Vec3>> {
+    let min_y = points
+        .iter()
+        .map(|point| point.y)
+        .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Less))
+        .ok_or(anyhow!("Navmesh has no vertices!"))?;
+    let mut points: Vec<Vec3> = points
+        .into_iter()
+        .map(|point| Vec3::new(point.x, min_y, point.z))
+        .collect();
+    points.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap());
+    points.dedup();
+    Ok(points)
+}
+
+/// Orders the vertices by walking the edges of the line mesh, starting with the end with the
+/// lowest x so tracks always run from left to right.
+fn connected_points(mesh: &Mesh, points: Vec<Vec3>) -> Result<Vec<Vec3>> {
+    // exported meshes may have split vertices, merge vertices at the same position
+    const WELD_DISTANCE: f32 = 1e-4;
+    let mut welded: Vec<Vec3> = Vec::new();
+    let vertices: Vec<usize> = points
+        .iter()
+        .map(|point| {
+            welded
+                .iter()
+                .position(|other| other.distance(*point) <= WELD_DISTANCE)
+                .unwrap_or_else(|| {
+                    welded.push(*point);
+                    welded.len() - 1
+                })
+        })
+        .collect();
+
+    let indices: Vec<usize> = match mesh.indices() {
+        Some(indices) => indices.iter().collect(),
+        None => (0..points.len()).collect(),
+    };
+    let edges: Vec<(usize, usize)> = match mesh.primitive_topology() {
+        PrimitiveTopology::LineList => indices.chunks_exact(2).map(|e| (e[0], e[1])).collect(),
+        PrimitiveTopology::LineStrip => indices.windows(2).map(|e| (e[0], e[1])).collect(),
+        topology => return Err(anyhow!("Navmesh {:?} is not a line mesh!", topology)),
+    };
+
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); welded.len()];
+    for (a, b) in edges {
+        let a = *vertices
+            .get(a)
+            .ok_or(anyhow!("Navmesh index out of bounds!"))?;
+        let b = *vertices
+            .get(b)
+            .ok_or(anyhow!("Navmesh index out of bounds!"))?;
+        if a != b && !neighbours[a].contains(&b) {
+            neighbours[a].push(b);
+            neighbours[b].push(a);
+        }
+    }
+
+    if let Some(vertex) = neighbours.iter().position(|n| n.len() > 2) {
+        return Err(anyhow!("Navmesh branches at {}!", welded[vertex]));
+    }
+    let start = (0..welded.len())
+        .filter(|vertex| neighbours[*vertex].len() < 2)
+        .min_by(|a, b| welded[*a].x.total_cmp(&welded[*b].x))
+        .ok_or(anyhow!(
+            "Navmesh has no ends, it is empty or a closed loop!"
+        ))?;
+
+    let mut ordered = vec![start];
+    let mut previous = None;
+    let mut current = start;
+    while let Some(next) = neighbours[current]
+        .iter()
+        .find(|n| Some(**n) != previous)
+        .copied()
+    {
+        ordered.push(next);
+        previous = Some(current);
+        current = next;
+    }
+    if ordered.len() != welded.len() {
+        return Err(anyhow!(
+            "Navmesh is disconnected, {} of {} vertices are not connected to the track!",
+            welded.len() - ordered.len(),
+            welded.len()
+        ));
+    }
+    Ok(ordered.into_iter().map(|vertex| welded[vertex]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::mesh::Indices;
+
+    use super::*;
+
+    fn mesh_from_points(points: &[[f32; 3]]) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, points.to_vec());
+        mesh
+    }
+
+    fn line_mesh(points: &[[f32; 3]], edges: &[u32]) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, points.to_vec());
+        mesh.set_indices(Some(Indices::U32(edges.to_vec())));
+        mesh
+    }
+
+    /// Straight track from x=0 to x=10 with duplicated and unordered vertices like a
+    /// triangulated strip exported from blender
+    fn straight_track() -> Track {
+        Track::from_mesh(&mesh_from_points(&[
+            [10.0, 0.5, 0.0],
+            [0.0, 0.0, 0.0],
+            [5.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [5.0, 0.2, 0.0],
+        ]))
+        .unwrap()
+    }
+
+    /// L-shaped track, 4 units along x then 3 units along z
+    fn bent_track() -> Track {
+        Track::new(vec![
+            Vec3::ZERO,
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(4.0, 0.0, 3.0),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn from_mesh_sorts_and_flattens() {
+        let track = straight_track();
+        assert_eq!(
+            track.points(),
+            &[
+                Vec3::ZERO,
+                Vec3::new(5.0, 0.0, 0.0),
+                Vec3::new(10.0, 0.0, 0.0)
+            ]
+        );
+        assert_eq!(track.segment_count(), 2);
+    }
+
+    #[test]
+    fn flat_meshes_with_heights_lose_them() {
+        let flat = mesh_from_points(&[[0.0, 1.0, 0.0], [5.0, 1.0, 2.0], [10.0, 1.01, 0.0]]);
+        assert_eq!(TrackMode::for_mesh(&flat).lost_height(&flat), None);
+        let ramp = mesh_from_points(&[[0.0, 0.0, 0.0], [5.0, 0.0, 2.0], [10.0, 4.0, 0.0]]);
+        assert_eq!(TrackMode::for_mesh(&ramp).lost_height(&ramp), Some(4.0));
+        let line = line_mesh(&[[0.0, 0.0, 0.0], [10.0, 4.0, 0.0]], &[0, 1]);
+        assert_eq!(TrackMode::for_mesh(&line).lost_height(&line), None);
+    }
+
+    #[test]
+    fn from_mesh_without_vertices_fails() {
+        assert!(Track::from_mesh(&mesh_from_points(&[])).is_err());
+        assert!(Track::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn length() {
+        assert_eq!(straight_track().length(), 10.0);
+        assert_eq!(bent_track().length(), 7.0);
+        assert_eq!(Track::new(vec![Vec3::ONE]).unwrap().length(), 0.0);
+    }
+
+    #[test]
+    fn position_at() {
+        let track = bent_track();
+        assert_eq!(track.position_at(0.0), Vec3::ZERO);
+        assert_eq!(track.position_at(2.0), Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(track.position_at(4.0), Vec3::new(4.0, 0.0, 0.0));
+        assert_eq!(track.position_at(5.5), Vec3::new(4.0, 0.0, 1.5));
+        assert_eq!(track.position_at(7.0), Vec3::new(4.0, 0.0, 3.0));
+    }
+
+    #[test]
+    fn position_at_is_clamped() {
+        let track = bent_track();
+        assert_eq!(track.position_at(-1.0), track.first());
+        assert_eq!(track.position_at(100.0), track.last());
+        assert_eq!(track.clamp(-1.0), 0.0);
+        assert_eq!(track.clamp(100.0), 7.0);
+    }
+
+    #[test]
+    fn segment_at() {
+        let track = bent_track();
+        assert_eq!(track.segment_at(-1.0), 0);
+        assert_eq!(track.segment_at(0.0), 0);
+        assert_eq!(track.segment_at(3.9), 0);
+        assert_eq!(track.segment_at(4.0), 1);
+        assert_eq!(track.segment_at(7.0), 1);
+        assert_eq!(track.segment_at(100.0), 1);
+    }
+
+    #[test]
+    fn tangent_at() {
+        let track = bent_track();
+        assert_eq!(track.tangent_at(1.0), Vec3::X);
+        assert_eq!(track.tangent_at(6.0), Vec3::Z);
+        assert_eq!(track.tangent_at(100.0), Vec3::Z);
+    }
+
+    #[test]
+    fn closest_point() {
+        let track = bent_track();
+        let projection = track.closest_point(Vec3::new(1.0, 2.0, -5.0));
+        assert_eq!(projection.segment, 0);
+        assert_eq!(projection.distance, 1.0);
+        assert_eq!(projection.position, Vec3::new(1.0, 0.0, 0.0));
+
+        let projection = track.closest_point(Vec3::new(10.0, 0.0, 2.0));
+        assert_eq!(projection.segment, 1);
+        assert_eq!(projection.distance, 6.0);
+        assert_eq!(projection.position, Vec3::new(4.0, 0.0, 2.0));
+
+        // beyond the ends the projection is clamped
+        assert_eq!(track.closest_point(Vec3::new(-3.0, 0.0, 0.0)).distance, 0.0);
+        assert_eq!(track.closest_point(Vec3::new(4.0, 0.0, 9.0)).distance, 7.0);
+    }
+
+    #[test]
+    fn distance_of_roundtrip() {
+        let track = straight_track();
+        for distance in [0.0, 2.5, 5.0, 7.25, 10.0] {
+            assert_eq!(track.distance_of(track.position_at(distance)), distance);
+        }
+    }
+
+    #[test]
+    fn trim_start_keeps_the_rest() {
+        let mut track = bent_track();
+        assert_eq!(track.trim_start(0.0), 0.0);
+        assert_eq!(track.trim_start(2.0), 2.0);
+        assert_eq!(
+            track.points(),
+            [
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(4.0, 0.0, 0.0),
+                Vec3::new(4.0, 0.0, 3.0)
+            ]
+        );
+        assert_eq!(track.length(), 5.0);
+        // cutting at a point doesn't duplicate it
+        assert_eq!(track.trim_start(2.0), 2.0);
+        assert_eq!(track.points().len(), 2);
+        assert_eq!(track.trim_start(10.0), 3.0);
+        assert_eq!(track.points(), [Vec3::new(4.0, 0.0, 3.0)]);
+        assert_eq!(track.length(), 0.0);
+    }
+
+    #[test]
+    fn append_stitches_at_the_end() {
+        let mut track = straight_track();
+        let length = track.length();
+        track
+            .append(&straight_track(), Vec3::new(10.0, 0.0, 0.0))
+            .unwrap();
+        assert_eq!(track.first(), straight_track().first());
+        assert_eq!(
+            track.last(),
+            straight_track().last() + Vec3::new(10.0, 0.0, 0.0)
+        );
+        assert!(track.points().windows(2).all(|p| p[0].x < p[1].x));
+        // existing distances stay the same
+        assert_eq!(track.distance_of(straight_track().last()), length);
+        assert_eq!(track.length(), 2.0 * length);
+        // overlapping or distant tracks don't meet
+        let mut track = straight_track();
+        assert!(track
+            .append(&straight_track(), Vec3::new(8.0, 0.0, 0.0))
+            .is_err());
+        assert!(track
+            .append(&straight_track(), Vec3::new(12.0, 0.0, 0.0))
+            .is_err());
+        assert_eq!(track.length(), length);
+    }
+
+    #[test]
+    fn append_keeps_tracks_doubling_back() {
+        // a ramp up along x that turns back, appended to itself at its end
+        let ramp = Track::new(vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(1.0, 4.0, 0.0),
+        ])
+        .unwrap();
+        let mut track = ramp.clone();
+        track.append(&ramp, ramp.last()).unwrap();
+        assert_eq!(
+            track.points(),
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(4.0, 0.0, 0.0),
+                Vec3::new(1.0, 4.0, 0.0),
+                Vec3::new(5.0, 4.0, 0.0),
+                Vec3::new(2.0, 8.0, 0.0),
+            ]
+        );
+        assert_eq!(track.length(), 2.0 * ramp.length());
+        assert_eq!(
+            track.position_at(ramp.length() + 2.0),
+            Vec3::new(3.0, 4.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn connected_keeps_heights_and_follows_edges() {
+        // a ramp going up along x, then a flat part going back in z, vertices are shuffled
+        let mesh = line_mesh(
+            &[
+                [4.0, 2.0, 0.0],
+                [0.0, 0.0, 0.0],
+                [4.0, 2.0, -3.0],
+                [2.0, 1.0, 0.0],
+            ],
+            &[2, 0, 1, 3, 3, 0],
+        );
+        assert_eq!(TrackMode::for_mesh(&mesh), TrackMode::Connected);
+        let track = Track::from_mesh_with_mode(&mesh, TrackMode::Connected).unwrap();
+        assert_eq!(
+            track.points(),
+            &[
+                Vec3::ZERO,
+                Vec3::new(2.0, 1.0, 0.0),
+                Vec3::new(4.0, 2.0, 0.0),
+                Vec3::new(4.0, 2.0, -3.0)
+            ]
+        );
+        assert_eq!(track.position_at(track.length()).y, 2.0);
+    }
+
+    #[test]
+    fn connected_welds_split_vertices() {
+        // two edges that don't share a vertex index but a position
+        let mesh = line_mesh(
+            &[
+                [0.0, 0.0, 0.0],
+                [1.0, 0.5, 0.0],
+                [1.0, 0.5, 0.0],
+                [2.0, 0.0, 0.0],
+            ],
+            &[2, 3, 0, 1],
+        );
+        let track = Track::from_mesh_with_mode(&mesh, TrackMode::Connected).unwrap();
+        assert_eq!(track.points().len(), 3);
+        assert_eq!(track.points()[1], Vec3::new(1.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn connected_line_strip() {
+        let mut mesh = Mesh::new(PrimitiveTopology::LineStrip);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[3.0, 0.0, 0.0], [2.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+        );
+        let track = Track::from_mesh_with_mode(&mesh, TrackMode::Connected).unwrap();
+        assert_eq!(track.first(), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(track.last(), Vec3::new(3.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn connected_branching_fails() {
+        let mesh = line_mesh(
+            &[
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [2.0, 0.0, 0.0],
+                [1.0, 0.0, 1.0],
+            ],
+            &[0, 1, 1, 2, 1, 3],
+        );
+        assert!(Track::from_mesh_with_mode(&mesh, TrackMode::Connected).is_err());
+    }
+
+    #[test]
+    fn connected_disconnected_fails() {
+        let mesh = line_mesh(
+            &[
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [2.0, 0.0, 0.0],
+                [3.0, 0.0, 0.0],
+            ],
+            &[0, 1, 2, 3],
+        );
+        assert!(Track::from_mesh_with_mode(&mesh, TrackMode::Connected).is_err());
+    }
+
+    #[test]
+    fn connected_closed_loop_fails() {
+        let mesh = line_mesh(
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0]],
+            &[0, 1, 1, 2, 2, 0],
+        );
+        assert!(Track::from_mesh_with_mode(&mesh, TrackMode::Connected).is_err());
+    }
+
+    #[test]
+    fn connected_requires_line_mesh() {
+        let mesh = mesh_from_points(&[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]]);
+        assert_eq!(TrackMode::for_mesh(&mesh), TrackMode::Flat);
+        assert!(Track::from_mesh_with_mode(&mesh, TrackMode::Connected).is_err());
     }
 }
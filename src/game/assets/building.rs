@@ -1,17 +1,272 @@
-use bevy::ecs::system::RunSystemOnce;
+use bevy::ecs::system::SystemParam;
 use bevy::gltf::{Gltf, GltfMesh, GltfNode};
 use bevy::prelude::*;
-use bevy::render::view::NoFrustumCulling;
+use bevy::render::primitives::Aabb;
+use thiserror::Error;
 
-use crate::common::track::Track;
-use crate::game::assets::GameAssets;
+use crate::common::graph::{ConnectorKind, TrackGraph};
+use crate::common::loader::AssetLoader;
+use crate::common::track::{Track, TrackMode};
+use crate::game::assets::BUILDING;
+use crate::game::building::marker::{MarkerKind, SceneMarker};
+use crate::game::states::GameState;
+
+/// A building segment scene and where it is stitched to its neighbours, measured along x in
+/// the scene's local space.
+#[derive(Debug, Clone)]
+pub struct BuildingSceneMetadata {
+    pub scene: Handle<Scene>,
+    /// Where the next segment starts
+    pub width: f32,
+    /// Where the previous segment ends, the overlap with it
+    pub offset: f32,
+    /// The marker nodes of the scene
+    pub markers: Vec<SceneMarker>,
+}
 
 #[derive(Resource)]
 pub struct BuildingResource {
-    pub scene_lopen: Handle<Scene>,
-    pub scene_ropen: Handle<Scene>,
-    pub scene_lropen: Handle<Scene>,
-    pub tracks: Vec<Track>,
+    pub lopen: BuildingSceneMetadata,
+    pub ropen: BuildingSceneMetadata,
+    pub lropen: BuildingSceneMetadata,
+    /// The tracks of a single segment in the glTF's local space, see `BuildingLayout` for the
+    /// tracks of the spawned building
+    pub tracks: TrackGraph,
+    /// The layer number of each track, the tracks are sorted by layer
+    pub layers: Vec<usize>,
+}
+
+/// A violation of the building glTF contract.
+#[derive(Error, Debug)]
+pub enum BuildingValidationError {
+    #[error("missing scene {0}")]
+    MissingScene(&'static str),
+    #[error("node {0}: can't parse the track layer number")]
+    InvalidTrackLayer(String),
+    #[error("node {name}: track layer {layer} is defined more than once")]
+    DuplicateTrackLayer { name: String, layer: usize },
+    #[error("node {0}: node is not loaded")]
+    MissingNode(String),
+    #[error("node {0}: node has no mesh")]
+    MissingMesh(String),
+    #[error("node {0}: mesh has no primitives")]
+    MissingPrimitive(String),
+    #[error(
+        "node {name}: flat track mesh has vertices {spread} apart in height, export ramps as line meshes"
+    )]
+    FlattenedTrack { name: String, spread: f32 },
+    #[error("node {name}: invalid track ({source})")]
+    InvalidTrack { name: String, source: anyhow::Error },
+    #[error("building has no tracks (nodes named track_l<N>)")]
+    NoTracks,
+    #[error("node {0}: can't parse the connector, expected <stairs|elevator>_l<N>_l<M>")]
+    InvalidConnector(String),
+    #[error("node {name}: connector references missing track layer {layer}")]
+    MissingConnectorLayer { name: String, layer: usize },
+    #[error("scene {0}: has no anchor nodes (anchor_left/anchor_right) and no meshes")]
+    MissingSceneBounds(&'static str),
+    #[error("scene {name}: left edge {left} is not before the right edge {right}")]
+    InvalidSceneBounds {
+        name: &'static str,
+        left: f32,
+        right: f32,
+    },
+}
+
+/// All violations found validating the building glTF.
+#[derive(Debug, Default)]
+pub struct BuildingValidationReport(pub Vec<BuildingValidationError>);
+
+impl std::fmt::Display for BuildingValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "building.gltf has {} problem(s):", self.0.len())?;
+        for error in self.0.iter() {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BuildingValidationReport {}
+
+/// The data extracted from a valid building glTF.
+struct ValidatedBuilding {
+    scene_lopen: Handle<Scene>,
+    scene_ropen: Handle<Scene>,
+    scene_lropen: Handle<Scene>,
+    tracks: TrackGraph,
+    layers: Vec<usize>,
+    track_meshes: Vec<Handle<Mesh>>,
+}
+
+const TRACK_PREFIX: &str = "track_l";
+const ANCHOR_LEFT_PREFIX: &str = "anchor_left";
+const ANCHOR_RIGHT_PREFIX: &str = "anchor_right";
+
+/// Parses connector node names like `stairs_l0_l1` or `elevator_l0_l3`, returns the kind
+/// and the two connected layers, or `None` if the node is not a connector.
+fn parse_connector_name(
+    name: &str,
+) -> Option<Result<(ConnectorKind, usize, usize), BuildingValidationError>> {
+    const CONNECTOR_PREFIXES: [(&str, ConnectorKind); 2] = [
+        ("stairs_l", ConnectorKind::Stairs),
+        ("elevator_l", ConnectorKind::Elevator),
+    ];
+    let (prefix, kind) = CONNECTOR_PREFIXES
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))?;
+    let layers = name[prefix.len()..]
+        .split_once("_l")
+        .and_then(|(from, to)| Some((from.parse().ok()?, to.parse().ok()?)));
+    Some(
+        layers
+            .map(|(from, to)| (*kind, from, to))
+            .ok_or(BuildingValidationError::InvalidConnector(name.to_string())),
+    )
+}
+
+/// Returns the first mesh of the glTF node.
+fn node_mesh(
+    name: &str,
+    node: &GltfNode,
+    gltf_meshes: &Assets<GltfMesh>,
+) -> Result<Handle<Mesh>, BuildingValidationError> {
+    // what the fuck is this API GltfNode -> GltfMesh -> GltfPrimitive -> Mesh
+    let mesh = node
+        .mesh
+        .as_ref()
+        .and_then(|mesh| gltf_meshes.get(mesh))
+        .ok_or(BuildingValidationError::MissingMesh(name.to_string()))?;
+    mesh.primitives
+        .first()
+        .map(|primitive| primitive.mesh.clone())
+        .ok_or(BuildingValidationError::MissingPrimitive(name.to_string()))
+}
+
+/// Validates the building glTF contract, collects every violation instead of stopping at the
+/// first one.
+fn validate_building(
+    building: &Gltf,
+    gltf_nodes: &Assets<GltfNode>,
+    gltf_meshes: &Assets<GltfMesh>,
+    meshes: &Assets<Mesh>,
+) -> Result<ValidatedBuilding, BuildingValidationReport> {
+    let mut errors = Vec::new();
+
+    let mut scene = |name: &'static str| {
+        let scene = building.named_scenes.get(name).cloned();
+        if scene.is_none() {
+            errors.push(BuildingValidationError::MissingScene(name));
+        }
+        scene
+    };
+    let scene_lopen = scene("LOpen");
+    let scene_ropen = scene("ROpen");
+    let scene_lropen = scene("LROpen");
+
+    // find tracks:
+    let mut tracks: Vec<(usize, Track, Handle<Mesh>)> = Vec::new();
+    for (name, node_handle) in building.named_nodes.iter() {
+        if !name.starts_with(TRACK_PREFIX) {
+            continue;
+        }
+        let Ok(layer) = name[TRACK_PREFIX.len()..].parse::<usize>() else {
+            errors.push(BuildingValidationError::InvalidTrackLayer(name.clone()));
+            continue;
+        };
+        let Some(node) = gltf_nodes.get(node_handle) else {
+            errors.push(BuildingValidationError::MissingNode(name.clone()));
+            continue;
+        };
+        let mesh_handle = match node_mesh(name, node, gltf_meshes) {
+            Ok(mesh_handle) => mesh_handle,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+        let Some(mesh) = meshes.get(&mesh_handle) else {
+            errors.push(BuildingValidationError::MissingMesh(name.clone()));
+            continue;
+        };
+        let mode = TrackMode::for_mesh(mesh);
+        if let Some(spread) = mode.lost_height(mesh) {
+            errors.push(BuildingValidationError::FlattenedTrack {
+                name: name.clone(),
+                spread,
+            });
+            continue;
+        }
+        match Track::from_mesh_with_mode(mesh, mode) {
+            Ok(track) => {
+                if tracks.iter().any(|(l, _, _)| *l == layer) {
+                    errors.push(BuildingValidationError::DuplicateTrackLayer {
+                        name: name.clone(),
+                        layer,
+                    });
+                }
+                tracks.push((layer, track, mesh_handle));
+            }
+            Err(source) => errors.push(BuildingValidationError::InvalidTrack {
+                name: name.clone(),
+                source,
+            }),
+        }
+    }
+    if tracks.is_empty() {
+        errors.push(BuildingValidationError::NoTracks);
+    }
+    tracks.sort_by_key(|(layer, _, _)| *layer);
+
+    // find connectors between the tracks:
+    let layers: Vec<usize> = tracks.iter().map(|(layer, _, _)| *layer).collect();
+    let track_meshes = tracks.iter().map(|(_, _, mesh)| mesh.clone()).collect();
+    let mut graph = TrackGraph::new(tracks.into_iter().map(|(_, track, _)| track).collect());
+    for (name, node_handle) in building.named_nodes.iter() {
+        let (kind, from, to) = match parse_connector_name(name) {
+            Some(Ok(connector)) => connector,
+            Some(Err(error)) => {
+                errors.push(error);
+                continue;
+            }
+            None => continue,
+        };
+        let index_of = |layer: usize| {
+            layers.iter().position(|l| *l == layer).ok_or_else(|| {
+                BuildingValidationError::MissingConnectorLayer {
+                    name: name.clone(),
+                    layer,
+                }
+            })
+        };
+        let (from, to) = match (index_of(from), index_of(to)) {
+            (Ok(from), Ok(to)) => (from, to),
+            (from, to) => {
+                errors.extend(from.err());
+                errors.extend(to.err());
+                continue;
+            }
+        };
+        if let Some(node) = gltf_nodes.get(node_handle) {
+            graph
+                .connect(kind, from, to, node.transform.translation)
+                .expect("connector tracks are validated");
+        }
+    }
+
+    match (scene_lopen, scene_ropen, scene_lropen) {
+        (Some(scene_lopen), Some(scene_ropen), Some(scene_lropen)) if errors.is_empty() => {
+            Ok(ValidatedBuilding {
+                scene_lopen,
+                scene_ropen,
+                scene_lropen,
+                tracks: graph,
+                layers,
+                track_meshes,
+            })
+        }
+        _ => Err(BuildingValidationReport(errors)),
+    }
 }
 
 fn hide_by_mesh_in_world(world: &mut World, mesh: AssetId<Mesh>) {
@@ -25,80 +280,560 @@ fn hide_by_mesh_in_world(world: &mut World, mesh: AssetId<Mesh>) {
     }
 }
 
-fn disable_frustum_culling_in_world(world: &mut World) {
-    world.run_system_once(
-        |query: Query<Entity, With<Handle<Mesh>>>, mut commands: Commands| {
-            for entity in query.iter() {
-                commands.entity(entity).insert(NoFrustumCulling);
+/// Returns the transform of the scene entity relative to the scene root.
+fn scene_transform(world: &World, entity: Entity) -> GlobalTransform {
+    let transform = world.get::<Transform>(entity).copied().unwrap_or_default();
+    match world.get::<Parent>(entity) {
+        Some(parent) => scene_transform(world, parent.get()) * transform,
+        None => GlobalTransform::from(transform),
+    }
+}
+
+/// Measures the left and right edge of a scene along x: the `anchor_left`/`anchor_right`
+/// nodes if the scene has them, otherwise the bounding box of its visible meshes.
+fn scene_edges(world: &mut World, hidden_meshes: &[Handle<Mesh>]) -> Option<(f32, f32)> {
+    let mut anchors = (None, None);
+    let mut bounds: Option<(f32, f32)> = None;
+    let mut query = world.query::<(Entity, Option<&Name>, Option<&Aabb>, Option<&Handle<Mesh>>)>();
+    for (entity, name, aabb, mesh) in query.iter(world) {
+        let transform = scene_transform(world, entity);
+        let name = name.map(Name::as_str).unwrap_or_default();
+        if name.starts_with(ANCHOR_LEFT_PREFIX) {
+            anchors.0 = Some(transform.translation().x);
+        } else if name.starts_with(ANCHOR_RIGHT_PREFIX) {
+            anchors.1 = Some(transform.translation().x);
+        }
+        let (Some(aabb), Some(mesh)) = (aabb, mesh) else {
+            continue;
+        };
+        if hidden_meshes.contains(mesh) {
+            continue;
+        }
+        let (min, max) = (aabb.min(), aabb.max());
+        for x in [min.x, max.x] {
+            for y in [min.y, max.y] {
+                for z in [min.z, max.z] {
+                    let corner = transform.transform_point(Vec3::new(x, y, z)).x;
+                    bounds = Some(match bounds {
+                        Some((left, right)) => (left.min(corner), right.max(corner)),
+                        None => (corner, corner),
+                    });
+                }
             }
-        },
+        }
+    }
+    match (anchors, bounds) {
+        ((Some(left), Some(right)), _) => Some((left, right)),
+        ((left, right), Some((min, max))) => Some((left.unwrap_or(min), right.unwrap_or(max))),
+        ((_, _), None) => None,
+    }
+}
+
+/// Returns the marker nodes of the scene, see [`MarkerKind`].
+fn scene_markers(world: &mut World) -> Vec<SceneMarker> {
+    let mut query = world.query::<(Entity, &Name)>();
+    query
+        .iter(world)
+        .filter_map(|(entity, name)| {
+            Some(SceneMarker {
+                kind: MarkerKind::from_name(name.as_str())?,
+                transform: scene_transform(world, entity).compute_transform(),
+            })
+        })
+        .collect()
+}
+
+/// Measures where the named scene is stitched to its neighbours and finds its markers.
+fn scene_metadata(
+    name: &'static str,
+    scene: Handle<Scene>,
+    scenes: &mut Assets<Scene>,
+    meshes: &Assets<Mesh>,
+    hidden_meshes: &[Handle<Mesh>],
+) -> Result<BuildingSceneMetadata, BuildingValidationError> {
+    let world = &mut scenes
+        .get_mut(&scene)
+        .ok_or(BuildingValidationError::MissingScene(name))?
+        .world;
+    update_aabbs_in_world(world, meshes);
+    let (left, right) = scene_edges(world, hidden_meshes)
+        .ok_or(BuildingValidationError::MissingSceneBounds(name))?;
+    if left >= right {
+        return Err(BuildingValidationError::InvalidSceneBounds { name, left, right });
+    }
+    Ok(BuildingSceneMetadata {
+        scene,
+        width: right,
+        offset: left,
+        markers: scene_markers(world),
+    })
+}
+
+/// Computes the bounding boxes of the scene meshes from their vertices. Bevy only adds the
+/// bounding boxes of spawned meshes, `scene_edges` measures the scene worlds which are never
+/// spawned, the spawned copies keep the computed boxes.
+fn update_aabbs_in_world(world: &mut World, meshes: &Assets<Mesh>) {
+    let aabbs: Vec<(Entity, Aabb)> = world
+        .query::<(Entity, &Handle<Mesh>)>()
+        .iter(world)
+        .filter_map(|(entity, mesh)| Some((entity, meshes.get(mesh)?.compute_aabb()?)))
+        .collect();
+    for (entity, aabb) in aabbs {
+        world.entity_mut(entity).insert(aabb);
+    }
+}
+
+/// The asset collections the building resource is extracted from
+#[derive(SystemParam)]
+pub struct BuildingAssets<'w> {
+    gltfs: Res<'w, Assets<Gltf>>,
+    gltf_nodes: Res<'w, Assets<GltfNode>>,
+    gltf_meshes: Res<'w, Assets<GltfMesh>>,
+    meshes: Res<'w, Assets<Mesh>>,
+    scenes: ResMut<'w, Assets<Scene>>,
+}
+
+/// Sent after the building glTF was modified and the BuildingResource was rebuilt
+#[derive(Event, Debug, Default)]
+pub struct BuildingReloadedEvent;
+
+/// Validates the building glTF and prepares its scenes
+fn build_building_resource(
+    handle: &Handle<Gltf>,
+    assets: &mut BuildingAssets,
+) -> Result<BuildingResource, BuildingValidationReport> {
+    let building = assets
+        .gltfs
+        .get(handle)
+        .expect("building glTF is not loaded");
+    let building_data = validate_building(
+        building,
+        &assets.gltf_nodes,
+        &assets.gltf_meshes,
+        &assets.meshes,
+    )?;
+
+    // hide track meshes in the scene:
+    for scene in building.scenes.iter() {
+        if let Some(scene) = assets.scenes.get_mut(scene.clone()) {
+            for mesh_handle in building_data.track_meshes.iter() {
+                hide_by_mesh_in_world(&mut scene.world, mesh_handle.id());
+            }
+        }
+    }
+
+    let hidden = &building_data.track_meshes;
+    let (scenes, meshes) = (&mut assets.scenes, &assets.meshes);
+    let metadata = (
+        scene_metadata("LOpen", building_data.scene_lopen, scenes, meshes, hidden),
+        scene_metadata("ROpen", building_data.scene_ropen, scenes, meshes, hidden),
+        scene_metadata("LROpen", building_data.scene_lropen, scenes, meshes, hidden),
     );
+    let (lopen, ropen, lropen) = match metadata {
+        (Ok(lopen), Ok(ropen), Ok(lropen)) => (lopen, ropen, lropen),
+        (lopen, ropen, lropen) => {
+            return Err(BuildingValidationReport(
+                [lopen.err(), ropen.err(), lropen.err()]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+            ))
+        }
+    };
+
+    info!(
+        "BuildingResource loaded ({} tracks, {} connectors)",
+        building_data.tracks.len(),
+        building_data.tracks.connectors().len()
+    );
+    for (name, metadata) in [("LOpen", &lopen), ("ROpen", &ropen), ("LROpen", &lropen)] {
+        info!(
+            "Building scene {}: width {}, offset {}, {} markers",
+            name,
+            metadata.width,
+            metadata.offset,
+            metadata.markers.len()
+        );
+    }
+
+    Ok(BuildingResource {
+        lopen,
+        ropen,
+        lropen,
+        tracks: building_data.tracks,
+        layers: building_data.layers,
+    })
 }
 
 pub fn prepare_building_resource(
     mut commands: Commands,
-    assets: Res<GameAssets>,
-    gltfs: Res<Assets<Gltf>>,
-    gltf_nodes: Res<Assets<GltfNode>>,
-    gltf_meshes: Res<Assets<GltfMesh>>,
-    meshes: Res<Assets<Mesh>>,
-    mut scenes: ResMut<Assets<Scene>>,
+    mut assets: BuildingAssets,
+    mut loader: ResMut<AssetLoader>,
+    mut state: ResMut<NextState<GameState>>,
 ) {
-    let building = gltfs.get(assets.building.id()).unwrap();
-
-    let scene_lopen = building
-        .named_scenes
-        .get("LOpen")
-        .expect("Missing scene LOpen")
-        .clone();
-    let scene_ropen = building
-        .named_scenes
-        .get("ROpen")
-        .expect("Missing scene ROpen")
-        .clone();
-    let scene_lropen = building
-        .named_scenes
-        .get("LROpen")
-        .expect("Missing scene LROpen")
-        .clone();
+    let handle = loader.get::<Gltf>(BUILDING).unwrap();
+    match build_building_resource(&handle, &mut assets) {
+        Ok(resource) => commands.insert_resource(resource),
+        Err(report) => {
+            error!("{}", report);
+            loader.add_failed(BUILDING, report.to_string());
+            state.set(GameState::LoadFailed);
+        }
+    }
+}
 
-    // find tracks:
-    const TRACK_PREFIX: &str = "track_l";
-    let mut tracks: Vec<_> = building
-        .named_nodes
-        .iter()
-        .filter(|(name, _)| name.starts_with(TRACK_PREFIX))
-        .map(|(name, node_handle)| {
-            let layer = name[TRACK_PREFIX.len()..].parse::<usize>().unwrap();
-            // what the fuck is this API GltfNode -> GltfMesh -> GltfPrimitive -> Mesh
-            let node = gltf_nodes.get(node_handle.id()).expect("no gltf node");
-            let mesh = gltf_meshes
-                .get(node.mesh.as_ref().unwrap())
-                .expect("no gltf mesh");
-            let mesh_handle = mesh.primitives.first().unwrap().mesh.clone();
-            let mesh = meshes.get(mesh_handle.clone()).unwrap();
-            let track = Track::from_mesh(mesh).unwrap();
-            (layer, track, mesh_handle)
-        })
-        .collect();
-    tracks.sort_by_key(|(layer, _, _)| *layer);
+/// Rebuilds the building resource when the building glTF is modified, keeps the previous
+/// building if the modified glTF is invalid.
+pub fn reload_building_resource_system(
+    mut asset_events: EventReader<AssetEvent<Gltf>>,
+    mut assets: BuildingAssets,
+    mut building_resource: ResMut<BuildingResource>,
+    loader: Res<AssetLoader>,
+    mut reloaded_events: EventWriter<BuildingReloadedEvent>,
+) {
+    let handle = loader.get::<Gltf>(BUILDING).unwrap();
+    let modified = asset_events
+        .read()
+        .any(|event| event.is_modified(handle.id()));
+    if !modified {
+        return;
+    }
 
-    // hide track meshes in the scene and disable frustum culling:
-    for scene in building.scenes.iter() {
-        if let Some(scene) = scenes.get_mut(scene.clone()) {
-            for (_, _, mesh_handle) in tracks.iter() {
-                hide_by_mesh_in_world(&mut scene.world, mesh_handle.id());
+    info!("building.gltf modified, rebuilding BuildingResource");
+    match build_building_resource(&handle, &mut assets) {
+        Ok(resource) => {
+            *building_resource = resource;
+            reloaded_events.send(BuildingReloadedEvent);
+        }
+        Err(report) => error!("Keeping the previous building, {}", report),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::gltf::GltfPrimitive;
+    use bevy::render::mesh::Indices;
+    use bevy::render::render_resource::PrimitiveTopology;
+
+    use super::*;
+
+    fn spawn_mesh(world: &mut World, x: f32, half_width: f32) -> Entity {
+        world
+            .spawn((
+                Transform::from_xyz(x, 0.0, 0.0),
+                Aabb::from_min_max(
+                    Vec3::new(-half_width, 0.0, -1.0),
+                    Vec3::new(half_width, 3.0, 1.0),
+                ),
+                Handle::<Mesh>::default(),
+            ))
+            .id()
+    }
+
+    /// A building glTF with the three segment scenes and the added nodes
+    struct TestBuilding {
+        gltf: Gltf,
+        nodes: Assets<GltfNode>,
+        gltf_meshes: Assets<GltfMesh>,
+        meshes: Assets<Mesh>,
+    }
+
+    impl TestBuilding {
+        fn new() -> Self {
+            let named_scenes = ["LOpen", "ROpen", "LROpen"]
+                .into_iter()
+                .map(|name| (name.to_string(), Handle::default()))
+                .collect();
+            Self {
+                gltf: Gltf {
+                    scenes: Vec::new(),
+                    named_scenes,
+                    meshes: Vec::new(),
+                    named_meshes: Default::default(),
+                    materials: Vec::new(),
+                    named_materials: Default::default(),
+                    nodes: Vec::new(),
+                    named_nodes: Default::default(),
+                    default_scene: None,
+                    animations: Vec::new(),
+                    named_animations: Default::default(),
+                },
+                nodes: Assets::default(),
+                gltf_meshes: Assets::default(),
+                meshes: Assets::default(),
             }
-            disable_frustum_culling_in_world(&mut scene.world);
         }
+
+        fn node(&mut self, name: &str, mesh: Option<Handle<GltfMesh>>, x: f32) -> &mut Self {
+            let node = self.nodes.add(GltfNode {
+                children: Vec::new(),
+                mesh,
+                transform: Transform::from_xyz(x, 0.0, 0.0),
+                extras: None,
+            });
+            self.gltf.named_nodes.insert(name.to_string(), node);
+            self
+        }
+
+        fn gltf_mesh(&mut self, meshes: Vec<Mesh>) -> Handle<GltfMesh> {
+            let primitives = meshes
+                .into_iter()
+                .map(|mesh| GltfPrimitive {
+                    mesh: self.meshes.add(mesh),
+                    material: None,
+                    extras: None,
+                    material_extras: None,
+                })
+                .collect();
+            self.gltf_meshes.add(GltfMesh {
+                primitives,
+                extras: None,
+            })
+        }
+
+        /// Adds a straight track from x=0 to x=10 at the height of the layer
+        fn track(&mut self, name: &str, layer: usize) -> &mut Self {
+            let y = layer as f32 * 4.0;
+            let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_POSITION,
+                vec![[0.0, y, 0.0], [10.0, y, 0.0]],
+            );
+            mesh.set_indices(Some(Indices::U32(vec![0, 1])));
+            let gltf_mesh = self.gltf_mesh(vec![mesh]);
+            self.node(name, Some(gltf_mesh), 0.0)
+        }
+
+        fn validate(&self) -> Result<ValidatedBuilding, BuildingValidationReport> {
+            validate_building(&self.gltf, &self.nodes, &self.gltf_meshes, &self.meshes)
+        }
+
+        /// Returns the only validation error
+        fn error(&self) -> BuildingValidationError {
+            let Err(mut report) = self.validate() else {
+                panic!("the building is valid");
+            };
+            assert_eq!(report.0.len(), 1, "{}", report);
+            report.0.remove(0)
+        }
+    }
+
+    /// Two floors connected by stairs
+    fn valid_building() -> TestBuilding {
+        let mut building = TestBuilding::new();
+        building
+            .track("track_l0", 0)
+            .track("track_l1", 1)
+            .node("stairs_l0_l1", None, 5.0);
+        building
+    }
+
+    #[test]
+    fn valid_building_has_tracks_and_connectors() {
+        let Ok(validated) = valid_building().validate() else {
+            panic!("the building is invalid");
+        };
+        assert_eq!(validated.layers, [0, 1]);
+        assert_eq!(validated.track_meshes.len(), 2);
+        assert_eq!(validated.tracks.len(), 2);
+        assert_eq!(validated.tracks.connectors().len(), 1);
+    }
+
+    #[test]
+    fn missing_scene() {
+        let mut building = valid_building();
+        building.gltf.named_scenes.remove("LROpen");
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::MissingScene("LROpen")
+        ));
+    }
+
+    #[test]
+    fn invalid_track_layer() {
+        let mut building = valid_building();
+        building.track("track_lx", 2);
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::InvalidTrackLayer(name) if name == "track_lx"
+        ));
     }
 
-    let tracks: Vec<Track> = tracks.into_iter().map(|(_, track, _)| track).collect();
+    #[test]
+    fn duplicate_track_layer() {
+        let mut building = valid_building();
+        building.track("track_l01", 1);
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::DuplicateTrackLayer { layer: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn missing_node() {
+        let mut building = valid_building();
+        building
+            .gltf
+            .named_nodes
+            .insert("track_l2".to_string(), Handle::default());
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::MissingNode(name) if name == "track_l2"
+        ));
+    }
+
+    #[test]
+    fn missing_mesh() {
+        let mut building = valid_building();
+        building.node("track_l2", None, 0.0);
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::MissingMesh(name) if name == "track_l2"
+        ));
+    }
+
+    #[test]
+    fn missing_primitive() {
+        let mut building = valid_building();
+        let gltf_mesh = building.gltf_mesh(Vec::new());
+        building.node("track_l2", Some(gltf_mesh), 0.0);
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::MissingPrimitive(name) if name == "track_l2"
+        ));
+    }
+
+    #[test]
+    fn flattened_track() {
+        let mut building = valid_building();
+        let mut ramp = Mesh::new(PrimitiveTopology::TriangleList);
+        ramp.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[0.0, 8.0, 0.0], [10.0, 10.0, 0.0], [10.0, 10.0, 1.0]],
+        );
+        let gltf_mesh = building.gltf_mesh(vec![ramp]);
+        building.node("track_l2", Some(gltf_mesh), 0.0);
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::FlattenedTrack { name, spread }
+                if name == "track_l2" && spread == 2.0
+        ));
+    }
 
-    commands.insert_resource(BuildingResource {
-        scene_lopen,
-        scene_ropen,
-        scene_lropen,
-        tracks,
-    });
+    #[test]
+    fn invalid_track() {
+        let mut building = valid_building();
+        let gltf_mesh = building.gltf_mesh(vec![Mesh::new(PrimitiveTopology::LineList)]);
+        building.node("track_l2", Some(gltf_mesh), 0.0);
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::InvalidTrack { name, .. } if name == "track_l2"
+        ));
+    }
+
+    #[test]
+    fn no_tracks() {
+        let building = TestBuilding::new();
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::NoTracks
+        ));
+    }
+
+    #[test]
+    fn invalid_connector() {
+        let mut building = valid_building();
+        building.node("elevator_l0", None, 8.0);
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::InvalidConnector(name) if name == "elevator_l0"
+        ));
+    }
+
+    #[test]
+    fn missing_connector_layer() {
+        let mut building = valid_building();
+        building.node("elevator_l0_l3", None, 8.0);
+        assert!(matches!(
+            building.error(),
+            BuildingValidationError::MissingConnectorLayer { layer: 3, .. }
+        ));
+    }
+
+    #[test]
+    fn missing_scene_bounds() {
+        let mut scenes = Assets::<Scene>::default();
+        let scene = scenes.add(Scene::new(World::new()));
+        assert!(matches!(
+            scene_metadata("LOpen", scene, &mut scenes, &Assets::default(), &[]),
+            Err(BuildingValidationError::MissingSceneBounds("LOpen"))
+        ));
+    }
+
+    #[test]
+    fn invalid_scene_bounds() {
+        let mut world = World::new();
+        world.spawn((Transform::from_xyz(5.0, 0.0, 0.0), Name::new("anchor_left")));
+        world.spawn((
+            Transform::from_xyz(1.0, 0.0, 0.0),
+            Name::new("anchor_right"),
+        ));
+        let mut scenes = Assets::<Scene>::default();
+        let scene = scenes.add(Scene::new(world));
+        assert!(matches!(
+            scene_metadata("LOpen", scene, &mut scenes, &Assets::default(), &[]),
+            Err(BuildingValidationError::InvalidSceneBounds { left, right, .. })
+                if left == 5.0 && right == 1.0
+        ));
+    }
+
+    #[test]
+    fn scene_meshes_get_bounding_boxes() {
+        let mut meshes = Assets::<Mesh>::default();
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[-1.0, 0.0, 0.0], [3.0, 2.0, 0.0], [0.0, 0.0, 1.0]],
+        );
+        let mut world = World::new();
+        let entity = world.spawn(meshes.add(mesh)).id();
+        let mut scenes = Assets::<Scene>::default();
+        let scene = scenes.add(Scene::new(world));
+        let metadata = scene_metadata("LOpen", scene.clone(), &mut scenes, &meshes, &[]).unwrap();
+        assert_eq!((metadata.offset, metadata.width), (-1.0, 3.0));
+        assert_eq!(
+            scenes.get(&scene).unwrap().world.get::<Aabb>(entity),
+            Some(&Aabb::from_min_max(
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(3.0, 2.0, 1.0)
+            ))
+        );
+    }
+
+    #[test]
+    fn scene_edges_from_meshes() {
+        let mut world = World::new();
+        let parent = world.spawn(Transform::from_xyz(10.0, 0.0, 0.0)).id();
+        let child = spawn_mesh(&mut world, 5.0, 2.0);
+        world.entity_mut(child).set_parent(parent);
+        spawn_mesh(&mut world, 0.0, 1.0);
+        assert_eq!(scene_edges(&mut world, &[]), Some((-1.0, 17.0)));
+        assert_eq!(scene_edges(&mut world, &[Handle::default()]), None);
+    }
+
+    #[test]
+    fn scene_edges_prefer_anchors() {
+        let mut world = World::new();
+        spawn_mesh(&mut world, 0.0, 10.0);
+        world.spawn((
+            Transform::from_xyz(-8.0, 0.0, 0.0),
+            Name::new("anchor_left"),
+        ));
+        assert_eq!(scene_edges(&mut world, &[]), Some((-8.0, 10.0)));
+        world.spawn((
+            Transform::from_xyz(9.0, 0.0, 0.0),
+            Name::new("anchor_right.001"),
+        ));
+        assert_eq!(scene_edges(&mut world, &[]), Some((-8.0, 9.0)));
+    }
 }
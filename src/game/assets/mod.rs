@@ -1,9 +1,19 @@
+use std::any::TypeId;
+use std::time::Duration;
+
 use anyhow::Result;
+use bevy::asset::UntypedAssetId;
 use bevy::gltf::Gltf;
 use bevy::prelude::*;
 
-use crate::common::loader::AssetLoader;
+use crate::common::loader::{
+    asset_event_system, AssetLoader, AssetLoaderTimeouts, AssetLoadingProgress,
+};
+use crate::common::manifest::{AssetManifest, ManifestAssetKind};
+use crate::common::ron_asset::RonAssetLoader;
 
+use super::building::level::BuildingLevel;
+use super::options::GameOptions;
 use super::states::{
     finished_init_system, finished_loaded_system, finished_loading_system, GameState,
 };
@@ -12,17 +22,48 @@ mod building;
 mod paperbox;
 mod paperman;
 
-pub use building::BuildingResource;
+pub use building::{BuildingReloadedEvent, BuildingResource, BuildingSceneMetadata};
+pub use paperbox::PaperboxResource;
 pub use paperman::PapermanResource;
 
+/// The manifest declaring all other assets
+const MANIFEST_PATH: &str = "game.manifest.ron";
+const MANIFEST: &str = "manifest";
+const MANIFEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Names of the manifest assets used by the game
+pub const PAPERMAN: &str = "paperman";
+pub const BUILDING: &str = "building";
+pub const PAPERBOX: &str = "paperbox";
+/// The default building level, overridden with `--level <name>`
+pub const LEVEL: &str = "level";
+const REQUIRED_ASSETS: [(&str, ManifestAssetKind); 3] = [
+    (PAPERMAN, ManifestAssetKind::Gltf),
+    (BUILDING, ManifestAssetKind::Gltf),
+    (PAPERBOX, ManifestAssetKind::Gltf),
+];
+
 pub struct GameAssetPlugin;
 
 impl Plugin for GameAssetPlugin {
     fn build(&self, app: &mut App) {
+        app.init_asset::<AssetManifest>();
+        app.register_asset_loader(RonAssetLoader::<AssetManifest>::new(&["manifest.ron"]));
+        app.add_event::<AssetLoadingProgress>();
+        app.add_event::<BuildingReloadedEvent>();
         app.add_systems(
             OnEnter(GameState::Init),
             load_assets_system.pipe(finished_init_system),
         );
+        app.add_systems(
+            PreUpdate,
+            (
+                asset_event_system::<AssetManifest>,
+                asset_event_system::<Gltf>,
+                asset_event_system::<Image>,
+                asset_event_system::<BuildingLevel>,
+            ),
+        );
         app.add_systems(
             Update,
             update_loading_system
@@ -38,39 +79,220 @@ impl Plugin for GameAssetPlugin {
                 finished_loaded_system,
             ),
         );
+        // rebuild before the update systems read the building
+        app.add_systems(
+            PreUpdate,
+            building::reload_building_resource_system.run_if(in_state(GameState::GameRunning)),
+        );
     }
 }
 
-#[derive(Resource)]
-pub struct GameAssets {
-    pub paperman: Handle<Gltf>,
-    pub building: Handle<Gltf>,
-    pub paperbox: Handle<Gltf>,
-}
-
 fn load_assets_system(mut commands: Commands, server: Res<AssetServer>) {
-    // animations: ["idle", "walking", "death", "tpose", "attack", "kick", "running"]
-    let paperman: Handle<Gltf> = server.load("paperman.gltf");
-    let building: Handle<Gltf> = server.load("building.gltf");
-    let paperbox: Handle<Gltf> = server.load("box.gltf");
+    let manifest: Handle<AssetManifest> = server.load(MANIFEST_PATH);
     let mut loader = AssetLoader::new();
+    loader.add(MANIFEST, manifest.untyped(), true);
+    loader.set_asset_timeout(MANIFEST, MANIFEST_TIMEOUT);
+    commands.insert_resource(loader);
+}
+
+/// Loads the assets declared in the manifest once it is loaded, fails the manifest if any of the
+/// assets the game needs is missing, optional or of the wrong kind.
+fn load_manifest_assets(
+    loader: &mut AssetLoader,
+    server: &AssetServer,
+    manifests: &Assets<AssetManifest>,
+    level: &str,
+) -> Result<()> {
+    let Some(manifest) = loader
+        .get::<AssetManifest>(MANIFEST)
+        .and_then(|manifest| manifests.get(manifest))
+    else {
+        return Ok(());
+    };
 
-    loader.add_pending(paperman.clone_weak().id().untyped());
-    loader.add_pending(building.clone_weak().id().untyped());
-    loader.add_pending(paperbox.clone_weak().id().untyped());
+    let invalid = invalid_required_assets(manifest, level);
+    if !invalid.is_empty() {
+        let reason = format!(
+            "manifest has invalid required assets: {}",
+            invalid.join(", ")
+        );
+        loader.add_failed(MANIFEST, reason.clone());
+        anyhow::bail!("Error loading assets:\n{}", reason);
+    }
 
-    commands.insert_resource(GameAssets {
-        paperman,
-        building,
-        paperbox,
+    loader.set_timeouts(AssetLoaderTimeouts {
+        asset: manifest.asset_timeout.map(Duration::from_secs_f32),
+        global: manifest.loading_timeout.map(Duration::from_secs_f32),
     });
-    commands.insert_resource(loader);
+    for (key, entry) in manifest.assets.iter() {
+        if loader.contains(key) {
+            continue;
+        }
+        let handle = match entry.kind {
+            ManifestAssetKind::Gltf => server.load::<Gltf>(&entry.path).untyped(),
+            ManifestAssetKind::Image => server.load::<Image>(&entry.path).untyped(),
+            ManifestAssetKind::Level => server.load::<BuildingLevel>(&entry.path).untyped(),
+        };
+        loader.add(key, handle, entry.required);
+        if let Some(timeout) = entry.timeout {
+            loader.set_asset_timeout(key, Duration::from_secs_f32(timeout));
+        }
+    }
+    Ok(())
+}
+
+/// Describes the assets the game needs that the manifest lacks, marks optional or declares as
+/// another kind.
+fn invalid_required_assets(manifest: &AssetManifest, level: &str) -> Vec<String> {
+    REQUIRED_ASSETS
+        .into_iter()
+        .chain([(level, ManifestAssetKind::Level)])
+        .filter_map(|(key, kind)| match manifest.assets.get(key) {
+            None => Some(format!("{} is missing", key)),
+            Some(entry) if !entry.required => Some(format!("{} is optional", key)),
+            Some(entry) if entry.kind != kind => {
+                Some(format!("{} is {:?} instead of {:?}", key, entry.kind, kind))
+            }
+            Some(_) => None,
+        })
+        .collect()
+}
+
+/// Returns the sub assets of a loaded glTF and the textures of its materials
+fn gltf_dependencies(
+    id: UntypedAssetId,
+    gltfs: &Assets<Gltf>,
+    materials: &Assets<StandardMaterial>,
+) -> Vec<UntypedAssetId> {
+    if id.type_id() != TypeId::of::<Gltf>() {
+        return Vec::new();
+    }
+    let Some(gltf) = gltfs.get(id.typed::<Gltf>()) else {
+        return Vec::new();
+    };
+    let textures = gltf
+        .materials
+        .iter()
+        .filter_map(|material| materials.get(material))
+        .flat_map(|material| {
+            [
+                &material.base_color_texture,
+                &material.emissive_texture,
+                &material.metallic_roughness_texture,
+                &material.normal_map_texture,
+                &material.occlusion_texture,
+            ]
+        })
+        .flatten()
+        .map(|texture| texture.id().untyped());
+    gltf.scenes
+        .iter()
+        .map(|scene| scene.id().untyped())
+        .chain(gltf.meshes.iter().map(|mesh| mesh.id().untyped()))
+        .chain(
+            gltf.materials
+                .iter()
+                .map(|material| material.id().untyped()),
+        )
+        .chain(textures)
+        .collect()
 }
 
 fn update_loading_system(
     mut loader: ResMut<AssetLoader>,
     server: Res<AssetServer>,
+    manifests: Res<Assets<AssetManifest>>,
+    gltfs: Res<Assets<Gltf>>,
+    materials: Res<Assets<StandardMaterial>>,
+    options: Res<GameOptions>,
+    mut progress_events: EventWriter<AssetLoadingProgress>,
 ) -> Result<bool> {
-    loader.update_loading_state(&server)?;
+    let mut result =
+        loader.update_loading_state(&server, |id| gltf_dependencies(id, &gltfs, &materials));
+    // the manifest only queues its assets once loaded, fail before nothing is pending
+    if result.is_ok() && loader.is_loaded(MANIFEST) {
+        result = load_manifest_assets(&mut loader, &server, &manifests, &options.level);
+    }
+    if let Some(progress) = loader.progress_changed() {
+        progress_events.send(progress);
+    }
+    result?;
     Ok(loader.is_finished())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bevy::asset::io::memory::Dir;
+
+    use super::*;
+    use crate::common::testing::memory_asset_app;
+
+    /// Runs the asset loading with the manifest until it leaves the loading states
+    fn load_with_manifest(manifest: &str) -> App {
+        let dir = Dir::default();
+        dir.insert_asset_text(Path::new(MANIFEST_PATH), manifest);
+        let mut app = memory_asset_app(&dir);
+        app.add_plugins(GameAssetPlugin);
+        app.init_asset::<Gltf>();
+        app.init_asset::<Image>();
+        app.init_asset::<StandardMaterial>();
+        app.init_asset::<BuildingLevel>();
+        app.add_state::<GameState>();
+        app.insert_resource(GameOptions::default());
+        for _ in 0..1000 {
+            app.update();
+            let state = *app.world.resource::<State<GameState>>().get();
+            if !matches!(state, GameState::Init | GameState::AssetsLoading) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        app
+    }
+
+    #[test]
+    fn manifest_without_building_fails_loading() {
+        let app = load_with_manifest(
+            r#"(assets: {
+                "paperman": (path: "paperman.gltf", kind: Gltf),
+                "paperbox": (path: "box.gltf", kind: Gltf),
+                "level": (path: "office.level.ron", kind: Level),
+            })"#,
+        );
+        assert_eq!(
+            *app.world.resource::<State<GameState>>().get(),
+            GameState::LoadFailed
+        );
+        let server = app.world.resource::<AssetServer>().clone();
+        let failures = app.world.resource::<AssetLoader>().failures(&server);
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].1.contains(BUILDING), "{:?}", failures);
+    }
+
+    #[test]
+    fn required_assets_must_be_required_and_of_their_kind() {
+        let manifest: AssetManifest = ron::de::from_str(
+            r#"(assets: {
+                "paperman": (path: "paperman.gltf", kind: Gltf, required: false),
+                "building": (path: "building.png", kind: Image),
+                "paperbox": (path: "box.gltf", kind: Gltf),
+                "level": (path: "office.level.ron", kind: Level),
+            })"#,
+        )
+        .unwrap();
+        assert_eq!(
+            invalid_required_assets(&manifest, LEVEL),
+            vec!["paperman is optional", "building is Image instead of Gltf"]
+        );
+        assert_eq!(
+            invalid_required_assets(&manifest, "basement"),
+            vec![
+                "paperman is optional",
+                "building is Image instead of Gltf",
+                "basement is missing"
+            ]
+        );
+    }
+}
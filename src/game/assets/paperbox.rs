@@ -1,7 +1,8 @@
 use bevy::gltf::Gltf;
 use bevy::prelude::*;
 
-use crate::game::assets::GameAssets;
+use crate::common::loader::AssetLoader;
+use crate::game::assets::PAPERBOX;
 
 #[derive(Resource)]
 pub struct PaperboxResource {
@@ -10,10 +11,10 @@ pub struct PaperboxResource {
 
 pub fn prepare_paperbox_resource(
     mut commands: Commands,
-    assets: Res<GameAssets>,
+    loader: Res<AssetLoader>,
     gltfs: Res<Assets<Gltf>>,
 ) {
-    let paperbox = gltfs.get(assets.paperbox.id()).unwrap();
+    let paperbox = gltfs.get(loader.get::<Gltf>(PAPERBOX).unwrap()).unwrap();
     let scene = paperbox.default_scene.clone().unwrap();
     commands.insert_resource(PaperboxResource { scene });
 }
@@ -2,7 +2,8 @@ use bevy::gltf::Gltf;
 use bevy::prelude::*;
 use bevy::utils::hashbrown::HashMap;
 
-use crate::game::assets::GameAssets;
+use crate::common::loader::AssetLoader;
+use crate::game::assets::PAPERMAN;
 
 #[derive(Resource)]
 pub struct PapermanResource {
@@ -12,10 +13,10 @@ pub struct PapermanResource {
 
 pub fn prepare_paperman_resource(
     mut commands: Commands,
-    assets: Res<GameAssets>,
+    loader: Res<AssetLoader>,
     gltfs: Res<Assets<Gltf>>,
 ) {
-    let paperman = gltfs.get(assets.paperman.id()).unwrap();
+    let paperman = gltfs.get(loader.get::<Gltf>(PAPERMAN).unwrap()).unwrap();
     let animations = paperman.named_animations.clone();
     let scene = paperman.default_scene.clone().unwrap();
 
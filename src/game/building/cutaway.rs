@@ -0,0 +1,182 @@
+use bevy::prelude::*;
+
+use crate::game::assets::BuildingResource;
+use crate::game::paperman::{Paperman, PapermanPosition};
+
+const WALL_FRONT: &str = "wall_front";
+const WALL_FRONT_LAYER_PREFIX: &str = "wall_front_l";
+const FLOOR_LAYER_PREFIX: &str = "floor_l";
+
+/// Building nodes hidden depending on paperman's floor, tagged by node name:
+///
+/// - `wall_front_l<N>`: front wall of layer N, hidden on layer N and below, `wall_front`
+///   without a layer is always hidden
+/// - `floor_l<N>`: floor geometry of layer N, hidden while paperman is below it
+///
+/// The names may end with a duplicate suffix like `.001`.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cutaway {
+    FrontWall(Option<usize>),
+    Floor(usize),
+}
+
+impl Cutaway {
+    /// Parses the node name, returns `None` for nodes that are not part of the cutaway.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = strip_duplicate_suffix(name);
+        if name == WALL_FRONT {
+            Some(Self::FrontWall(None))
+        } else if let Some(layer) = name.strip_prefix(WALL_FRONT_LAYER_PREFIX) {
+            parse_layer(layer).map(|layer| Self::FrontWall(Some(layer)))
+        } else {
+            name.strip_prefix(FLOOR_LAYER_PREFIX)
+                .and_then(parse_layer)
+                .map(Self::Floor)
+        }
+    }
+
+    /// Returns true if the node blocks the view on paperman's layer.
+    pub fn hidden_on(&self, layer: usize) -> bool {
+        match *self {
+            Self::FrontWall(Some(wall)) => wall >= layer,
+            Self::FrontWall(None) => true,
+            Self::Floor(floor) => floor > layer,
+        }
+    }
+}
+
+/// Visibility of a cutaway node before it was tagged, restored when it is no longer hidden
+#[derive(Component, Debug, Clone, Copy)]
+pub struct CutawayVisibility(Visibility);
+
+/// Removes the `.001` suffix Blender appends to duplicate names.
+fn strip_duplicate_suffix(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((base, suffix)) if is_number(suffix) => base,
+        _ => name,
+    }
+}
+
+fn is_number(digits: &str) -> bool {
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Parses the layer number following the `_l` of the name.
+fn parse_layer(digits: &str) -> Option<usize> {
+    is_number(digits).then(|| digits.parse().ok()).flatten()
+}
+
+/// Tags the building nodes spawned by the scenes
+pub fn tag_cutaway_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Name, Option<&Visibility>), Added<Name>>,
+) {
+    for (entity, name, visibility) in query.iter() {
+        if let Some(cutaway) = Cutaway::from_name(name.as_str()) {
+            let visibility = CutawayVisibility(visibility.copied().unwrap_or_default());
+            commands.entity(entity).insert((cutaway, visibility));
+        }
+    }
+}
+
+/// Hides the tagged nodes between the camera and paperman's floor
+pub fn update_cutaway_system(
+    building: Res<BuildingResource>,
+    paperman: Query<&PapermanPosition, With<Paperman>>,
+    mut query: Query<(Ref<Cutaway>, &CutawayVisibility, &mut Visibility)>,
+    mut current_layer: Local<Option<usize>>,
+) {
+    let Ok(position) = paperman.get_single() else {
+        return;
+    };
+    let Some(layer) = building.layers.get(position.track).copied() else {
+        return;
+    };
+    let floor_changed = *current_layer != Some(layer);
+    *current_layer = Some(layer);
+    for (cutaway, original, mut visibility) in query.iter_mut() {
+        if !floor_changed && !cutaway.is_added() {
+            continue;
+        }
+        let next = if cutaway.hidden_on(layer) {
+            Visibility::Hidden
+        } else {
+            original.0
+        };
+        if *visibility != next {
+            *visibility = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::testing::building_app;
+
+    #[test]
+    fn from_name() {
+        assert_eq!(
+            Cutaway::from_name("wall_front_l1"),
+            Some(Cutaway::FrontWall(Some(1)))
+        );
+        assert_eq!(
+            Cutaway::from_name("wall_front_l12.003"),
+            Some(Cutaway::FrontWall(Some(12)))
+        );
+        assert_eq!(
+            Cutaway::from_name("wall_front.001"),
+            Some(Cutaway::FrontWall(None))
+        );
+        assert_eq!(Cutaway::from_name("floor_l2"), Some(Cutaway::Floor(2)));
+        assert_eq!(Cutaway::from_name("floor"), None);
+        assert_eq!(Cutaway::from_name("wall_back_l1"), None);
+        assert_eq!(Cutaway::from_name("wall_frontdesk"), None);
+        assert_eq!(Cutaway::from_name("wall_front_lamp"), None);
+        assert_eq!(Cutaway::from_name("wall_front_l1b"), None);
+        assert_eq!(Cutaway::from_name("floor_l2.backup"), None);
+    }
+
+    #[test]
+    fn hidden_on() {
+        assert!(Cutaway::FrontWall(Some(1)).hidden_on(1));
+        assert!(Cutaway::FrontWall(Some(2)).hidden_on(1));
+        assert!(!Cutaway::FrontWall(Some(0)).hidden_on(1));
+        assert!(Cutaway::FrontWall(None).hidden_on(0));
+        assert!(Cutaway::Floor(2).hidden_on(1));
+        assert!(!Cutaway::Floor(1).hidden_on(1));
+    }
+
+    #[test]
+    fn unhidden_nodes_get_their_visibility_back() {
+        let mut app = building_app(2, 10.0);
+        app.add_systems(Update, (tag_cutaway_system, update_cutaway_system).chain());
+        let paperman = app
+            .world
+            .spawn((
+                Paperman,
+                PapermanPosition {
+                    track: 0,
+                    distance: 0.0,
+                },
+            ))
+            .id();
+        let wall = app
+            .world
+            .spawn((Name::new("wall_front_l0"), Visibility::Visible))
+            .id();
+        app.update();
+        app.update();
+        assert_eq!(app.world.get::<Visibility>(wall), Some(&Visibility::Hidden));
+
+        app.world
+            .get_mut::<PapermanPosition>(paperman)
+            .unwrap()
+            .track = 1;
+        app.update();
+        assert_eq!(
+            app.world.get::<Visibility>(wall),
+            Some(&Visibility::Visible)
+        );
+    }
+}
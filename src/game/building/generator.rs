@@ -0,0 +1,217 @@
+use std::ops::RangeInclusive;
+
+use thiserror::Error;
+
+use crate::common::random::SeededRandom;
+
+use super::BuildingSceneDirection;
+
+/// Parameters of the random building generator.
+#[derive(Debug, Clone)]
+pub struct GeneratorParams {
+    /// Number of segments, including both ends of the building
+    pub length: RangeInclusive<usize>,
+    /// Relative weights the segments are picked with, segments with weight 0 are never picked
+    pub weights: Vec<(BuildingSceneDirection, u32)>,
+    /// Number of floor tracks the paperboxes are placed on
+    pub floors: usize,
+    /// Number of paperboxes placed in every segment
+    pub boxes: RangeInclusive<usize>,
+}
+
+impl Default for GeneratorParams {
+    fn default() -> Self {
+        Self {
+            length: 3..=8,
+            weights: vec![
+                (BuildingSceneDirection::Right, 1),
+                (BuildingSceneDirection::LeftRight, 1),
+                (BuildingSceneDirection::Left, 1),
+            ],
+            floors: 1,
+            boxes: 0..=2,
+        }
+    }
+}
+
+/// A paperbox placed by the generator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxPlacement {
+    /// Index of the segment the paperbox is placed in
+    pub segment: usize,
+    /// Index of the floor track, see [`LevelBox`](super::level::LevelBox)
+    pub track: usize,
+    /// Position within the segment from its left (0.0) to its right end (1.0)
+    pub position: f32,
+}
+
+/// A generated building, the segments from left to right and the paperboxes placed in them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedBuilding {
+    pub segments: Vec<BuildingSceneDirection>,
+    pub boxes: Vec<BoxPlacement>,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum GeneratorError {
+    #[error("a building needs at least 2 segments, got {0}")]
+    TooShort(usize),
+    #[error("no segment with a weight fits at segment {0}")]
+    NoSegment(usize),
+    #[error("paperboxes need at least one floor")]
+    NoFloors,
+}
+
+/// Generates a valid building layout from left to right, the same seed and parameters always
+/// generate the same layout.
+pub fn generate_building(
+    seed: u64,
+    params: &GeneratorParams,
+) -> Result<GeneratedBuilding, GeneratorError> {
+    let mut random = SeededRandom::new(seed);
+    let length = random.range(params.length.clone());
+    if length < 2 {
+        return Err(GeneratorError::TooShort(length));
+    }
+    if params.floors == 0 && *params.boxes.end() > 0 {
+        return Err(GeneratorError::NoFloors);
+    }
+    let mut segments: Vec<BuildingSceneDirection> = Vec::with_capacity(length);
+    for index in 0..length {
+        // the ends need to be closed, everything in between open towards its neighbours
+        let fits = |direction: &BuildingSceneDirection| {
+            direction.open_left() == (index > 0) && direction.open_right() == (index < length - 1)
+        };
+        let weights: Vec<u32> = params
+            .weights
+            .iter()
+            .map(|(direction, weight)| if fits(direction) { *weight } else { 0 })
+            .collect();
+        let picked = random
+            .weighted(&weights)
+            .ok_or(GeneratorError::NoSegment(index))?;
+        segments.push(params.weights[picked].0);
+    }
+    // placed after all segments, so the boxes don't change the segments of a seed
+    let mut boxes = Vec::new();
+    for segment in 0..length {
+        for _ in 0..random.range(params.boxes.clone()) {
+            boxes.push(BoxPlacement {
+                segment,
+                track: random.below(params.floors as u64) as usize,
+                position: random.unit(),
+            });
+        }
+    }
+    Ok(GeneratedBuilding { segments, boxes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::building::layout::validate_layout;
+    use BuildingSceneDirection::{Left, LeftRight, Right};
+
+    #[test]
+    fn generated_layouts_are_valid() {
+        let params = GeneratorParams::default();
+        for seed in 0..100 {
+            let segments = generate_building(seed, &params).unwrap().segments;
+            assert!(params.length.contains(&segments.len()));
+            assert!(validate_layout(&segments).is_ok(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_building() {
+        let params = GeneratorParams::default();
+        for seed in [0, 1, 1234, u64::MAX] {
+            assert_eq!(
+                generate_building(seed, &params),
+                generate_building(seed, &params)
+            );
+        }
+    }
+
+    #[test]
+    fn seeds_generate_different_lengths() {
+        let params = GeneratorParams::default();
+        let lengths: Vec<_> = (0..20)
+            .map(|seed| generate_building(seed, &params).unwrap().segments.len())
+            .collect();
+        assert!(lengths.iter().any(|length| *length != lengths[0]));
+    }
+
+    #[test]
+    fn fixed_length() {
+        let params = GeneratorParams {
+            length: 4..=4,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_building(9, &params).unwrap().segments,
+            [Right, LeftRight, LeftRight, Left]
+        );
+    }
+
+    #[test]
+    fn boxes_are_placed_on_the_floors() {
+        let params = GeneratorParams {
+            floors: 3,
+            boxes: 1..=3,
+            ..Default::default()
+        };
+        for seed in 0..100 {
+            let building = generate_building(seed, &params).unwrap();
+            let length = building.segments.len();
+            assert!(building.boxes.len() >= length && building.boxes.len() <= 3 * length);
+            for placement in building.boxes.iter() {
+                assert!(placement.segment < length);
+                assert!(placement.track < params.floors);
+                assert!((0.0..1.0).contains(&placement.position));
+            }
+            // placements don't change the segments
+            let no_boxes = GeneratorParams {
+                boxes: 0..=0,
+                ..params.clone()
+            };
+            assert_eq!(
+                generate_building(seed, &no_boxes).unwrap().segments,
+                building.segments
+            );
+        }
+        let floors: Vec<_> = (0..20)
+            .flat_map(|seed| generate_building(seed, &params).unwrap().boxes)
+            .map(|placement| placement.track)
+            .collect();
+        assert!((0..params.floors).all(|floor| floors.contains(&floor)));
+    }
+
+    #[test]
+    fn invalid_params() {
+        let params = GeneratorParams {
+            length: 1..=1,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_building(0, &params),
+            Err(GeneratorError::TooShort(1))
+        );
+
+        let params = GeneratorParams {
+            length: 3..=3,
+            weights: vec![(Right, 1), (LeftRight, 0), (Left, 1)],
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_building(0, &params),
+            Err(GeneratorError::NoSegment(1))
+        );
+
+        let params = GeneratorParams {
+            floors: 0,
+            ..Default::default()
+        };
+        assert_eq!(generate_building(0, &params), Err(GeneratorError::NoFloors));
+    }
+}
@@ -0,0 +1,170 @@
+use thiserror::Error;
+
+use super::generator::GeneratorError;
+use super::level::BuildingLevel;
+use super::BuildingSceneDirection;
+
+/// A building layout where the open sides of neighbouring segments don't match.
+#[derive(Error, Debug, PartialEq)]
+pub enum LayoutError {
+    #[error("layout has no segments")]
+    Empty,
+    #[error("level {0} is not loaded")]
+    MissingLevel(String),
+    #[error("segment {index}: unknown scene {scene}")]
+    UnknownScene { index: usize, scene: String },
+    #[error("segment {0}: the first segment needs to be closed on the left")]
+    OpenStart(usize),
+    #[error("segment {0}: the last segment needs to be closed on the right")]
+    OpenEnd(usize),
+    #[error(
+        "segments {index} and {}: {left:?} and {right:?} have a wall in the corridor",
+        index + 1
+    )]
+    Mismatch {
+        index: usize,
+        left: BuildingSceneDirection,
+        right: BuildingSceneDirection,
+    },
+    #[error("can't generate the building: {0}")]
+    Generator(GeneratorError),
+}
+
+/// All violations found validating a building layout.
+#[derive(Debug, Default)]
+pub struct LayoutValidationReport(pub Vec<LayoutError>);
+
+impl std::fmt::Display for LayoutValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "building layout has {} problem(s):", self.0.len())?;
+        for error in self.0.iter() {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LayoutValidationReport {}
+
+/// Validates a layout of segments from left to right: neighbours need to be open towards each
+/// other and the building needs to be closed at both ends.
+pub fn validate_layout(segments: &[BuildingSceneDirection]) -> Result<(), LayoutValidationReport> {
+    let (Some(first), Some(last)) = (segments.first(), segments.last()) else {
+        return Err(LayoutValidationReport(vec![LayoutError::Empty]));
+    };
+    let mut errors = Vec::new();
+    if first.open_left() {
+        errors.push(LayoutError::OpenStart(0));
+    }
+    for (index, pair) in segments.windows(2).enumerate() {
+        // closed sides are only allowed at the ends of the building
+        if !(pair[0].open_right() && pair[1].open_left()) {
+            errors.push(LayoutError::Mismatch {
+                index,
+                left: pair[0],
+                right: pair[1],
+            });
+        }
+    }
+    if last.open_right() {
+        errors.push(LayoutError::OpenEnd(segments.len() - 1));
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(LayoutValidationReport(errors))
+    }
+}
+
+/// Returns the segment directions of the level, validated with [`validate_layout`].
+pub fn level_layout(
+    level: &BuildingLevel,
+) -> Result<Vec<BuildingSceneDirection>, LayoutValidationReport> {
+    let mut errors = Vec::new();
+    let mut segments = Vec::with_capacity(level.segments.len());
+    for (index, segment) in level.segments.iter().enumerate() {
+        match BuildingSceneDirection::from_scene_name(&segment.scene) {
+            Some(direction) => segments.push(direction),
+            None => errors.push(LayoutError::UnknownScene {
+                index,
+                scene: segment.scene.clone(),
+            }),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(LayoutValidationReport(errors));
+    }
+    validate_layout(&segments)?;
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::building::level::LevelSegment;
+    use BuildingSceneDirection::{Left, LeftRight, Right};
+
+    fn errors(segments: &[BuildingSceneDirection]) -> Vec<LayoutError> {
+        validate_layout(segments).err().unwrap_or_default().0
+    }
+
+    #[test]
+    fn valid_layouts() {
+        assert!(validate_layout(&[Right, Left]).is_ok());
+        assert!(validate_layout(&[Right, LeftRight, LeftRight, Left]).is_ok());
+    }
+
+    #[test]
+    fn empty_layout() {
+        assert_eq!(errors(&[]), [LayoutError::Empty]);
+    }
+
+    #[test]
+    fn open_ends() {
+        assert_eq!(errors(&[LeftRight, Left]), [LayoutError::OpenStart(0)]);
+        assert_eq!(errors(&[Right, LeftRight]), [LayoutError::OpenEnd(1)]);
+    }
+
+    #[test]
+    fn wall_in_the_corridor() {
+        assert_eq!(
+            errors(&[Right, Left, Right, Left]),
+            [LayoutError::Mismatch {
+                index: 1,
+                left: Left,
+                right: Right
+            }]
+        );
+        assert_eq!(
+            errors(&[Right, Right, Left]),
+            [LayoutError::Mismatch {
+                index: 0,
+                left: Right,
+                right: Right
+            }]
+        );
+    }
+
+    #[test]
+    fn unknown_level_scene() {
+        let level = BuildingLevel {
+            segments: ["ROpen", "Lobby", "LOpen"]
+                .into_iter()
+                .map(|scene| LevelSegment {
+                    scene: scene.to_string(),
+                    width: None,
+                    offset: None,
+                })
+                .collect(),
+            boxes: Vec::new(),
+            delivery: None,
+        };
+        assert_eq!(
+            level_layout(&level).unwrap_err().0,
+            [LayoutError::UnknownScene {
+                index: 1,
+                scene: "Lobby".to_string()
+            }]
+        );
+    }
+}
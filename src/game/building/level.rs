@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use serde::Deserialize;
+
+/// A building layout, loaded from `*.level.ron` files.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct BuildingLevel {
+    /// Segments placed from left to right
+    pub segments: Vec<LevelSegment>,
+    /// Paperboxes placed on the floor tracks
+    #[serde(default)]
+    pub boxes: Vec<LevelBox>,
+    /// Turns the level into a delivery objective
+    #[serde(default)]
+    pub delivery: Option<LevelDelivery>,
+}
+
+/// A building segment, by the name of its scene in the building glTF.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LevelSegment {
+    pub scene: String,
+    /// Overrides the width of the scene
+    #[serde(default)]
+    pub width: Option<f32>,
+    /// Overrides the overlap with the previous segment
+    #[serde(default)]
+    pub offset: Option<f32>,
+}
+
+/// A paperbox, by the index of its floor track (from the bottom, not the layer number of the
+/// floor) and the distance along the track from the left end of the building.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LevelBox {
+    pub track: usize,
+    pub distance: f32,
+}
+
+/// Paperboxes to carry from pickup to drop-off zones within a time budget.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LevelDelivery {
+    /// Time budget in seconds
+    pub time: f32,
+    /// Number of delivered paperboxes completing the level
+    pub deliveries: usize,
+    pub zones: Vec<LevelZone>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ZoneKind {
+    Pickup,
+    DropOff,
+}
+
+/// A stretch of a floor track, by its track index and distances like [`LevelBox`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct LevelZone {
+    pub kind: ZoneKind,
+    pub track: usize,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl LevelZone {
+    pub fn contains(&self, track: usize, distance: f32) -> bool {
+        self.track == track && (self.start..=self.end).contains(&distance)
+    }
+
+    /// Returns true if both zones share a part of the same track
+    pub fn overlaps(&self, other: &LevelZone) -> bool {
+        self.track == other.track && self.start <= other.end && other.start <= self.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_office_level() {
+        let level: BuildingLevel =
+            ron::de::from_str(include_str!("../../../assets/office.level.ron")).unwrap();
+        let scenes: Vec<_> = level.segments.iter().map(|s| s.scene.as_str()).collect();
+        assert_eq!(scenes, ["ROpen", "LROpen", "LOpen"]);
+        assert!(!level.boxes.is_empty());
+        assert!(level.delivery.is_some());
+    }
+
+    #[test]
+    fn parse_delivery() {
+        let level: BuildingLevel = ron::de::from_str(
+            r#"(
+                segments: [(scene: "LROpen")],
+                delivery: Some((
+                    time: 60.0,
+                    deliveries: 1,
+                    zones: [(kind: DropOff, track: 1, start: 10.0, end: 20.0)],
+                )),
+            )"#,
+        )
+        .unwrap();
+        let zone = &level.delivery.unwrap().zones[0];
+        assert_eq!(zone.kind, ZoneKind::DropOff);
+        assert!(zone.contains(1, 15.0));
+        assert!(!zone.contains(0, 15.0));
+        assert!(!zone.contains(1, 25.0));
+    }
+
+    #[test]
+    fn parse_overrides() {
+        let level: BuildingLevel =
+            ron::de::from_str(r#"(segments: [(scene: "LROpen", width: Some(60.0))])"#).unwrap();
+        assert_eq!(level.segments[0].width, Some(60.0));
+        assert_eq!(level.segments[0].offset, None);
+    }
+}
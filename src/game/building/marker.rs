@@ -0,0 +1,74 @@
+use bevy::prelude::*;
+
+/// The gameplay element a marker node places, parsed from the node name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkerKind {
+    /// `spawn_player`: where paperman starts
+    SpawnPlayer,
+    /// `spawn_box`: where a paperbox is placed
+    SpawnBox,
+    /// `spawn_npc`: where a non-player character is placed
+    SpawnNpc,
+    /// `trigger_<name>`: a named gameplay trigger
+    Trigger(String),
+}
+
+impl MarkerKind {
+    /// Parses marker node names, ignores the numbered suffix blender adds to duplicated
+    /// nodes (`spawn_box.001`), returns `None` for nodes that are not markers.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.split_once('.').map_or(name, |(name, _)| name);
+        if name.starts_with("spawn_player") {
+            Some(Self::SpawnPlayer)
+        } else if name.starts_with("spawn_box") {
+            Some(Self::SpawnBox)
+        } else if name.starts_with("spawn_npc") {
+            Some(Self::SpawnNpc)
+        } else {
+            name.strip_prefix("trigger_")
+                .filter(|trigger| !trigger.is_empty())
+                .map(|trigger| Self::Trigger(trigger.to_string()))
+        }
+    }
+}
+
+/// A marker node of a building scene, with its transform relative to the scene root.
+#[derive(Debug, Clone)]
+pub struct SceneMarker {
+    pub kind: MarkerKind,
+    pub transform: Transform,
+}
+
+/// A marker placed in the world, spawned as child of its building segment so it is despawned
+/// together with the segment. The `GlobalTransform` is set in world space at spawn time.
+#[derive(Component, Debug, Clone)]
+pub struct BuildingMarker {
+    pub kind: MarkerKind,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_name() {
+        assert_eq!(
+            MarkerKind::from_name("spawn_player"),
+            Some(MarkerKind::SpawnPlayer)
+        );
+        assert_eq!(
+            MarkerKind::from_name("spawn_box.004"),
+            Some(MarkerKind::SpawnBox)
+        );
+        assert_eq!(
+            MarkerKind::from_name("spawn_npc_boss"),
+            Some(MarkerKind::SpawnNpc)
+        );
+        assert_eq!(
+            MarkerKind::from_name("trigger_exit.001"),
+            Some(MarkerKind::Trigger("exit".to_string()))
+        );
+        assert_eq!(MarkerKind::from_name("trigger_"), None);
+        assert_eq!(MarkerKind::from_name("track_l0"), None);
+    }
+}
@@ -1,78 +1,534 @@
 use bevy::prelude::*;
 
-use super::{assets::BuildingResource, states::GameState};
+use crate::common::graph::{TrackGraph, TrackPoint};
+use crate::common::loader::AssetLoader;
+use crate::common::ron_asset::RonAssetLoader;
+
+use self::generator::{generate_building, BoxPlacement, GeneratorParams};
+use self::layout::{level_layout, validate_layout, LayoutError, LayoutValidationReport};
+use self::level::BuildingLevel;
+use self::marker::{BuildingMarker, MarkerKind};
+
+use super::{
+    assets::{BuildingReloadedEvent, BuildingResource, BuildingSceneMetadata},
+    options::GameOptions,
+    paperman::Paperman,
+    states::GameState,
+};
+
+pub mod cutaway;
+pub mod generator;
+pub mod layout;
+pub mod level;
+pub mod marker;
 
 pub struct BuildingPlugin;
 
 impl Plugin for BuildingPlugin {
     fn build(&self, app: &mut App) {
+        app.init_asset::<BuildingLevel>();
+        app.register_asset_loader(RonAssetLoader::<BuildingLevel>::new(&["level.ron"]));
+        app.init_resource::<BuildingLayout>();
+        app.add_event::<BuildingRespawnedEvent>();
+        app.add_event::<BuildingTrimmedEvent>();
+        app.add_event::<LevelRestartEvent>();
         app.add_systems(OnEnter(GameState::GameLoading), prepare_scenes_system);
+        app.add_systems(
+            Update,
+            (
+                respawn_scenes_system,
+                stream_endless_scenes_system.run_if(is_endless),
+                cutaway::tag_cutaway_system,
+                cutaway::update_cutaway_system,
+            )
+                .chain()
+                .run_if(in_state(GameState::GameRunning)),
+        );
     }
 }
 
-#[derive(Debug)]
+/// How the building is laid out
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BuildingMode {
+    /// A single office laid out from the level file, closed at both ends
+    #[default]
+    Fixed,
+    /// Open segments are spawned ahead of paperman and despawned behind him
+    Endless,
+    /// A single office randomly generated from the seed
+    Generated(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BuildingSceneDirection {
     Left,
     LeftRight,
     Right,
 }
 
-#[derive(Component, Debug)]
+impl BuildingSceneDirection {
+    /// Returns the direction of the scene with the given name in the building glTF
+    pub fn from_scene_name(name: &str) -> Option<Self> {
+        match name {
+            "LOpen" => Some(Self::Left),
+            "LROpen" => Some(Self::LeftRight),
+            "ROpen" => Some(Self::Right),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the segment continues into the segment on its left
+    pub fn open_left(&self) -> bool {
+        matches!(self, Self::Left | Self::LeftRight)
+    }
+
+    /// Returns true if the segment continues into the segment on its right
+    pub fn open_right(&self) -> bool {
+        matches!(self, Self::Right | Self::LeftRight)
+    }
+
+    fn metadata<'a>(&self, building: &'a BuildingResource) -> &'a BuildingSceneMetadata {
+        match self {
+            Self::Left => &building.lopen,
+            Self::LeftRight => &building.lropen,
+            Self::Right => &building.ropen,
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone)]
 pub struct BuildingScene {
     pub direction: BuildingSceneDirection,
     pub width: f32,
     pub offset: f32,
 }
 
-const BUILDING_LR_WIDTH: f32 = 65.3827;
-const BUILDING_L_WIDTH: f32 = 68.8429;
-const BUILDING_R_WIDTH: f32 = 67.251;
-const BUILDING_LR_OFFSET: f32 = 1.86839;
-const BUILDING_L_OFFSET: f32 = 1.86839;
+impl BuildingScene {
+    /// A scene with the width and offset measured from the building glTF
+    fn new(direction: BuildingSceneDirection, building: &BuildingResource) -> Self {
+        let metadata = direction.metadata(building);
+        Self {
+            direction,
+            width: metadata.width,
+            offset: metadata.offset,
+        }
+    }
+}
+
+/// Tracks where the next building segment is placed
+#[derive(Resource, Debug, Default)]
+pub struct BuildingLayout {
+    /// The x coordinate where the last placed segment ends
+    pub end: f32,
+    /// The tracks of all placed segments in world space, stitched into one track per floor
+    pub tracks: TrackGraph,
+    /// Where paperman starts, the first `spawn_player` marker in world space
+    pub player_spawn: Option<Vec3>,
+    /// Paperboxes placed by the generator, on the layout tracks
+    pub boxes: Vec<TrackPoint>,
+}
 
-fn prepare_scenes_system(mut commands: Commands, building: Res<BuildingResource>) {
-    commands.spawn((
-        SceneBundle {
-            scene: building.scene_ropen.clone(),
-            transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
-            ..Default::default()
-        },
-        BuildingScene {
-            direction: BuildingSceneDirection::Right,
-            width: BUILDING_R_WIDTH,
-            offset: 0.0,
-        },
-    ));
-    commands.spawn((
-        SceneBundle {
-            scene: building.scene_lropen.clone(),
-            transform: Transform::from_translation(Vec3::new(
-                BUILDING_R_WIDTH - BUILDING_LR_OFFSET,
-                0.0,
-                0.0,
-            )),
-            ..Default::default()
-        },
-        BuildingScene {
-            direction: BuildingSceneDirection::LeftRight,
-            width: BUILDING_LR_WIDTH,
-            offset: BUILDING_LR_OFFSET,
-        },
-    ));
-    commands.spawn((
-        SceneBundle {
-            scene: building.scene_lopen.clone(),
-            transform: Transform::from_translation(Vec3::new(
-                (BUILDING_R_WIDTH - BUILDING_LR_OFFSET) + (BUILDING_LR_WIDTH - BUILDING_L_OFFSET),
-                0.0,
-                0.0,
-            )),
+/// Sent after all building scenes were replaced and the layout tracks were rebuilt
+#[derive(Event, Debug, Default)]
+pub struct BuildingRespawnedEvent;
+
+/// Sent to play the level again, the paperboxes, paperman and the delivery objective go back to
+/// their start in the current building
+#[derive(Event, Debug, Default)]
+pub struct LevelRestartEvent;
+
+/// Sent after the endless building dropped the tracks of the segments despawned behind
+/// paperman, distances on the layout tracks shift back by the length removed from their track.
+#[derive(Event, Debug, Default)]
+pub struct BuildingTrimmedEvent {
+    /// Length removed from the start of every layout track
+    pub removed: Vec<f32>,
+}
+
+impl BuildingTrimmedEvent {
+    /// Returns the distance after trimming, `None` if it was on the removed part
+    pub fn shifted(&self, track: usize, distance: f32) -> Option<f32> {
+        let removed = self.removed.get(track).copied().unwrap_or(0.0);
+        (distance >= removed).then_some(distance - removed)
+    }
+}
+
+/// Endless mode keeps segments spawned this far ahead of paperman
+const ENDLESS_SPAWN_AHEAD: f32 = 150.0;
+/// Endless mode despawns segments ending this far behind paperman
+const ENDLESS_DESPAWN_BEHIND: f32 = 150.0;
+
+/// Returns the level selected in the options, if it is loaded
+pub fn selected_level<'a>(
+    options: &GameOptions,
+    loader: &AssetLoader,
+    levels: &'a Assets<BuildingLevel>,
+) -> Option<&'a BuildingLevel> {
+    loader
+        .get::<BuildingLevel>(&options.level)
+        .and_then(|level| levels.get(level))
+}
+
+fn is_endless(options: Res<GameOptions>) -> bool {
+    options.building_mode == BuildingMode::Endless
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn prepare_scenes_system(
+    mut commands: Commands,
+    mut layout: ResMut<BuildingLayout>,
+    building: Res<BuildingResource>,
+    options: Res<GameOptions>,
+    mut loader: ResMut<AssetLoader>,
+    levels: Res<Assets<BuildingLevel>>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    let level = selected_level(&options, &loader, &levels);
+    match planned_scenes(&building, level, &options) {
+        Ok((scenes, boxes)) => spawn_scenes(
+            &mut commands,
+            &mut layout,
+            &building,
+            scenes,
+            &boxes,
+            options.building_mode,
+        ),
+        Err(report) => {
+            error!("{}", report);
+            loader.add_failed(&options.level, report.to_string());
+            state.set(GameState::LoadFailed);
+        }
+    }
+}
+
+/// Replaces the building scenes after the building or the level was reloaded, keeps the
+/// previous building if the new layout is invalid.
+#[allow(clippy::too_many_arguments)]
+pub fn respawn_scenes_system(
+    mut commands: Commands,
+    mut events: EventReader<BuildingReloadedEvent>,
+    mut respawned_events: EventWriter<BuildingRespawnedEvent>,
+    mut level_events: EventReader<AssetEvent<BuildingLevel>>,
+    mut layout: ResMut<BuildingLayout>,
+    building: Res<BuildingResource>,
+    options: Res<GameOptions>,
+    loader: Res<AssetLoader>,
+    levels: Res<Assets<BuildingLevel>>,
+    query: Query<Entity, With<BuildingScene>>,
+) {
+    let level_handle = loader.get::<BuildingLevel>(&options.level);
+    let level_modified = level_events.read().any(|event| {
+        level_handle
+            .as_ref()
+            .is_some_and(|handle| event.is_modified(handle.id()))
+    });
+    if events.read().last().is_none() && !level_modified {
+        return;
+    }
+    let level = level_handle.and_then(|level| levels.get(level));
+    let (scenes, boxes) = match planned_scenes(&building, level, &options) {
+        Ok(planned) => planned,
+        Err(report) => {
+            error!("Keeping the previous building layout, {}", report);
+            return;
+        }
+    };
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_scenes(
+        &mut commands,
+        &mut layout,
+        &building,
+        scenes,
+        &boxes,
+        options.building_mode,
+    );
+    respawned_events.send(BuildingRespawnedEvent);
+}
+
+/// Spawns segments ahead of paperman and despawns the ones far behind him together with their
+/// part of the layout tracks
+pub fn stream_endless_scenes_system(
+    mut commands: Commands,
+    mut events: EventWriter<BuildingTrimmedEvent>,
+    mut layout: ResMut<BuildingLayout>,
+    building: Res<BuildingResource>,
+    paperman: Query<&Transform, With<Paperman>>,
+    scenes: Query<(Entity, &BuildingScene, &Transform)>,
+) {
+    let Ok(paperman) = paperman.get_single() else {
+        return;
+    };
+    let x = paperman.translation.x;
+    while layout.end < x + ENDLESS_SPAWN_AHEAD {
+        spawn_segment(
+            &mut commands,
+            &mut layout,
+            &building,
+            BuildingScene::new(BuildingSceneDirection::LeftRight, &building),
+        );
+    }
+    let mut trim = None;
+    for (entity, scene, transform) in scenes.iter() {
+        let end = transform.translation.x + scene.width;
+        if end < x - ENDLESS_DESPAWN_BEHIND {
+            commands.entity(entity).despawn_recursive();
+            trim = Some(trim.map_or(end, |trim: f32| trim.max(end)));
+        }
+    }
+    if let Some(end) = trim {
+        let removed = layout.tracks.trim_before(end);
+        events.send(BuildingTrimmedEvent { removed });
+    }
+}
+
+/// Spawns the next segment at the end of the layout, stitched to the previous segment, and
+/// appends its tracks to the layout tracks
+fn spawn_segment(
+    commands: &mut Commands,
+    layout: &mut BuildingLayout,
+    building: &BuildingResource,
+    scene: BuildingScene,
+) {
+    let x = layout.end - scene.offset;
+    layout.end = x + scene.width;
+    if let Err(error) = layout
+        .tracks
+        .append(&building.tracks, Vec3::new(x, 0.0, 0.0))
+    {
+        error!(
+            "Failed stitching the tracks of the segment at {}: {}",
+            x, error
+        );
+    }
+    let metadata = scene.direction.metadata(building);
+    let origin = Transform::from_translation(Vec3::new(x, 0.0, 0.0));
+    commands
+        .spawn((
+            SceneBundle {
+                scene: metadata.scene.clone(),
+                transform: origin,
+                ..Default::default()
+            },
+            scene,
+        ))
+        .with_children(|parent| {
+            for marker in metadata.markers.iter() {
+                let world = GlobalTransform::from(origin) * GlobalTransform::from(marker.transform);
+                if marker.kind == MarkerKind::SpawnPlayer && layout.player_spawn.is_none() {
+                    layout.player_spawn = Some(world.translation());
+                }
+                parent.spawn((
+                    TransformBundle {
+                        local: marker.transform,
+                        global: world,
+                    },
+                    BuildingMarker {
+                        kind: marker.kind.clone(),
+                    },
+                ));
+            }
+        });
+}
+
+/// Returns the validated segments the building starts with, the selected level in fixed mode,
+/// and the paperboxes placed by the generator
+fn planned_scenes(
+    building: &BuildingResource,
+    level: Option<&BuildingLevel>,
+    options: &GameOptions,
+) -> Result<(Vec<BuildingScene>, Vec<BoxPlacement>), LayoutValidationReport> {
+    match options.building_mode {
+        BuildingMode::Fixed => {
+            let level = level.ok_or_else(|| {
+                LayoutValidationReport(vec![LayoutError::MissingLevel(options.level.clone())])
+            })?;
+            let directions = level_layout(level)?;
+            let scenes = directions
+                .into_iter()
+                .zip(level.segments.iter())
+                .map(|(direction, segment)| {
+                    let metadata = direction.metadata(building);
+                    BuildingScene {
+                        direction,
+                        width: segment.width.unwrap_or(metadata.width),
+                        offset: segment.offset.unwrap_or(metadata.offset),
+                    }
+                })
+                .collect();
+            Ok((scenes, Vec::new()))
+        }
+        BuildingMode::Generated(seed) => {
+            info!("Generating the building with seed {}", seed);
+            let params = GeneratorParams {
+                floors: building.tracks.len(),
+                ..Default::default()
+            };
+            let generated = generate_building(seed, &params)
+                .map_err(|error| LayoutValidationReport(vec![LayoutError::Generator(error)]))?;
+            validate_layout(&generated.segments)?;
+            let scenes = generated
+                .segments
+                .into_iter()
+                .map(|direction| BuildingScene::new(direction, building))
+                .collect();
+            Ok((scenes, generated.boxes))
+        }
+        BuildingMode::Endless => Ok((
+            vec![BuildingScene::new(BuildingSceneDirection::Right, building)],
+            Vec::new(),
+        )),
+    }
+}
+
+fn spawn_scenes(
+    commands: &mut Commands,
+    layout: &mut BuildingLayout,
+    building: &BuildingResource,
+    scenes: Vec<BuildingScene>,
+    boxes: &[BoxPlacement],
+    mode: BuildingMode,
+) {
+    *layout = BuildingLayout::default();
+    let mut spans = Vec::with_capacity(scenes.len());
+    for scene in scenes {
+        spans.push((layout.end - scene.offset, scene.width));
+        spawn_segment(commands, layout, building, scene);
+    }
+    for placement in boxes {
+        let (Some((x, width)), Some(track)) = (
+            spans.get(placement.segment),
+            layout.tracks.tracks().get(placement.track),
+        ) else {
+            warn!("Skipping generated paperbox {:?}", placement);
+            continue;
+        };
+        let position = Vec3::new(
+            x + placement.position * width,
+            track.first().y,
+            track.first().z,
+        );
+        let distance = track.closest_point(position).distance;
+        layout.boxes.push(TrackPoint {
+            track: placement.track,
+            distance,
+        });
+    }
+    if mode == BuildingMode::Endless {
+        while layout.end < ENDLESS_SPAWN_AHEAD {
+            let scene = BuildingScene::new(BuildingSceneDirection::LeftRight, building);
+            spawn_segment(commands, layout, building, scene);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+    use crate::game::testing::{building_app, straight_building, FLOOR_HEIGHT};
+
+    #[test]
+    fn endless_building_trims_the_tracks_behind() {
+        let mut app = building_app(2, 10.0);
+        app.add_event::<BuildingTrimmedEvent>();
+        app.add_systems(Update, stream_endless_scenes_system);
+        app.world.resource_mut::<BuildingResource>().lropen.width = 10.0;
+        *app.world.resource_mut::<BuildingLayout>() = BuildingLayout::default();
+        let paperman = app
+            .world
+            .spawn((Paperman, Transform::from_xyz(0.0, 0.0, 0.0)))
+            .id();
+        app.update();
+        let layout = app.world.resource::<BuildingLayout>();
+        assert_eq!(layout.end, ENDLESS_SPAWN_AHEAD);
+        assert_eq!(layout.tracks[0].first(), Vec3::ZERO);
+
+        app.world
+            .get_mut::<Transform>(paperman)
+            .unwrap()
+            .translation
+            .x = 200.0;
+        app.update();
+        // the segments ending before x=50 are gone, with their part of the tracks
+        let first = app
+            .world
+            .query::<(&BuildingScene, &Transform)>()
+            .iter(&app.world)
+            .map(|(_, transform)| transform.translation.x)
+            .fold(f32::MAX, f32::min);
+        assert_eq!(first, 40.0);
+        let layout = app.world.resource::<BuildingLayout>();
+        assert_eq!(layout.end, 200.0 + ENDLESS_SPAWN_AHEAD);
+        assert_eq!(layout.tracks[1].first(), Vec3::new(40.0, FLOOR_HEIGHT, 0.0));
+        assert_eq!(layout.tracks[1].length(), layout.end - 40.0);
+        let events = app.world.resource::<Events<BuildingTrimmedEvent>>();
+        let mut reader = events.get_reader();
+        let trimmed: Vec<_> = reader.read(events).collect();
+        assert_eq!(trimmed.len(), 1);
+        assert_eq!(trimmed[0].removed, [40.0, 40.0]);
+    }
+
+    #[test]
+    fn fixed_building_needs_the_level() {
+        let options = GameOptions {
+            level: "lobby".to_string(),
             ..Default::default()
-        },
-        BuildingScene {
-            direction: BuildingSceneDirection::Left,
-            width: BUILDING_L_WIDTH,
-            offset: BUILDING_L_OFFSET,
-        },
-    ));
+        };
+        let report = planned_scenes(&straight_building(1, 10.0), None, &options).unwrap_err();
+        assert_eq!(report.0, [LayoutError::MissingLevel("lobby".to_string())]);
+    }
+
+    #[test]
+    fn generated_boxes_are_placed_on_the_layout_tracks() {
+        let mut building = straight_building(2, 10.0);
+        building.lropen.width = 10.0;
+        let scenes = vec![BuildingScene::new(BuildingSceneDirection::LeftRight, &building); 2];
+        let boxes = [
+            BoxPlacement {
+                segment: 1,
+                track: 1,
+                position: 0.5,
+            },
+            BoxPlacement {
+                segment: 0,
+                track: 0,
+                position: 0.25,
+            },
+            // skipped, the building has no third floor
+            BoxPlacement {
+                segment: 0,
+                track: 2,
+                position: 0.0,
+            },
+        ];
+        let mut layout = BuildingLayout::default();
+        let mut queue = CommandQueue::default();
+        let world = World::new();
+        let mut commands = Commands::new(&mut queue, &world);
+        spawn_scenes(
+            &mut commands,
+            &mut layout,
+            &building,
+            scenes,
+            &boxes,
+            BuildingMode::Generated(0),
+        );
+        assert_eq!(layout.tracks[1].last(), Vec3::new(20.0, FLOOR_HEIGHT, 0.0));
+        assert_eq!(
+            layout.boxes,
+            [
+                TrackPoint {
+                    track: 1,
+                    distance: 15.0
+                },
+                TrackPoint {
+                    track: 0,
+                    distance: 2.5
+                },
+            ]
+        );
+    }
 }
@@ -0,0 +1,500 @@
+use bevy::prelude::*;
+
+use crate::common::loader::AssetLoader;
+
+use self::objective::{DeliveryObjective, DeliveryOutcome};
+
+use super::{
+    building::{
+        level::{BuildingLevel, ZoneKind},
+        prepare_scenes_system, selected_level, BuildingLayout, BuildingMode, LevelRestartEvent,
+    },
+    options::GameOptions,
+    paperbox::{Paperbox, PaperboxCarried, PaperboxMotion, PAPERBOX_SIZE},
+    states::{finished_level_system, GameState},
+    ui::{color, text, TEXT_COLOR},
+};
+
+pub mod objective;
+
+pub struct DeliveryPlugin;
+
+impl Plugin for DeliveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PaperboxDeliveredEvent>();
+        app.add_systems(
+            OnEnter(GameState::GameLoading),
+            prepare_delivery_system.after(prepare_scenes_system),
+        );
+        app.add_systems(OnEnter(GameState::GameRunning), spawn_delivery_hud_system);
+        app.add_systems(
+            Update,
+            (
+                reset_delivery_system,
+                (
+                    load_cargo_system,
+                    deliver_paperbox_system,
+                    delivery_timer_system.pipe(finished_level_system),
+                    update_delivery_hud_system,
+                    spawn_delivery_popup_system,
+                    draw_delivery_zones_system,
+                )
+                    .chain()
+                    .run_if(resource_exists::<DeliveryObjective>()),
+            )
+                .chain()
+                .run_if(in_state(GameState::GameRunning)),
+        );
+        // the last popups also fade out over the result panel
+        app.add_systems(Update, update_delivery_popup_system);
+    }
+}
+
+/// Sent for every paperbox carried from a pickup zone to a drop-off zone
+#[derive(Event, Debug)]
+pub struct PaperboxDeliveredEvent {
+    pub points: u32,
+}
+
+/// A paperbox that was in a pickup zone and counts once it rests in a drop-off zone
+#[derive(Component, Debug)]
+pub struct DeliveryCargo {
+    /// Index of the pickup zone in the level delivery
+    pub pickup: usize,
+}
+
+/// Shows the time left, the delivered paperboxes and the score
+#[derive(Component)]
+struct DeliveryHud;
+
+/// Shows the points of a delivery below the HUD, rising and fading out until the timer ends
+#[derive(Component)]
+struct DeliveryPopup(Timer);
+
+const POPUP_COLOR: &str = "#80ff80";
+const POPUP_SECONDS: f32 = 1.5;
+/// Distance in pixels the popup rises while fading out
+const POPUP_RISE: f32 = 24.0;
+const POPUP_TOP: f32 = 48.0;
+
+const PICKUP_COLOR: Color = Color::CYAN;
+const DROP_OFF_COLOR: Color = Color::GREEN;
+/// Zones are drawn along the track with this resolution
+const ZONE_STEP: f32 = 1.0;
+
+/// Starts the delivery objective of the level, if it defines one
+fn prepare_delivery_system(
+    mut commands: Commands,
+    options: Res<GameOptions>,
+    mut loader: ResMut<AssetLoader>,
+    levels: Res<Assets<BuildingLevel>>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    commands.remove_resource::<DeliveryObjective>();
+    // only the fixed building is laid out from the level
+    if options.building_mode != BuildingMode::Fixed {
+        return;
+    }
+    let level = selected_level(&options, &loader, &levels);
+    let Some(delivery) = level.and_then(|level| level.delivery.as_ref()) else {
+        return;
+    };
+    match DeliveryObjective::new(delivery) {
+        Ok(objective) => {
+            info!(
+                "delivery objective: {} paperboxes in {}s",
+                objective.required, objective.time_left
+            );
+            commands.insert_resource(objective);
+        }
+        Err(error) => {
+            error!("Invalid delivery objective: {}", error);
+            loader.add_failed(
+                &options.level,
+                format!("invalid delivery objective: {}", error),
+            );
+            state.set(GameState::LoadFailed);
+        }
+    }
+}
+
+/// Restarts the delivery objective when the level restarts, keeps the previous objective if the
+/// new one is invalid.
+#[allow(clippy::too_many_arguments)]
+fn reset_delivery_system(
+    mut commands: Commands,
+    mut events: EventReader<LevelRestartEvent>,
+    objective: Option<ResMut<DeliveryObjective>>,
+    hud: Query<Entity, With<DeliveryHud>>,
+    options: Res<GameOptions>,
+    loader: Res<AssetLoader>,
+    levels: Res<Assets<BuildingLevel>>,
+) {
+    if events.read().last().is_none() || options.building_mode != BuildingMode::Fixed {
+        return;
+    }
+    let level = selected_level(&options, &loader, &levels);
+    let Some(delivery) = level.and_then(|level| level.delivery.as_ref()) else {
+        commands.remove_resource::<DeliveryObjective>();
+        for entity in hud.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    };
+    match (DeliveryObjective::new(delivery), objective) {
+        (Ok(new), Some(mut objective)) => *objective = new,
+        (Ok(new), None) => {
+            commands.insert_resource(new);
+            spawn_delivery_hud(&mut commands);
+        }
+        (Err(error), _) => error!("Keeping the previous delivery objective, {}", error),
+    }
+}
+
+/// Marks the paperboxes standing in a pickup zone as cargo
+#[allow(clippy::type_complexity)]
+fn load_cargo_system(
+    mut commands: Commands,
+    objective: Res<DeliveryObjective>,
+    query: Query<(Entity, &Paperbox), (Without<DeliveryCargo>, Without<PaperboxCarried>)>,
+) {
+    for (entity, paperbox) in query.iter() {
+        if let Some(pickup) = objective.zone_at(ZoneKind::Pickup, paperbox.track, paperbox.distance)
+        {
+            commands.entity(entity).insert(DeliveryCargo { pickup });
+        }
+    }
+}
+
+/// Counts and removes the cargo resting in a drop-off zone
+fn deliver_paperbox_system(
+    mut commands: Commands,
+    mut objective: ResMut<DeliveryObjective>,
+    mut events: EventWriter<PaperboxDeliveredEvent>,
+    query: Query<(Entity, &Paperbox, &PaperboxMotion, &DeliveryCargo), Without<PaperboxCarried>>,
+) {
+    for (entity, paperbox, motion, cargo) in query.iter() {
+        if !motion.is_resting() {
+            continue;
+        }
+        let drop_off = objective.zone_at(ZoneKind::DropOff, paperbox.track, paperbox.distance);
+        let Some(drop_off) = drop_off else {
+            continue;
+        };
+        let Some(points) = objective.deliver() else {
+            return;
+        };
+        info!(
+            "delivered paperbox {:?} from zone {} to zone {}, {} points",
+            entity, cargo.pickup, drop_off, points
+        );
+        commands.entity(entity).despawn_recursive();
+        events.send(PaperboxDeliveredEvent { points });
+    }
+}
+
+/// Counts down the time budget, returns true once the objective is over
+fn delivery_timer_system(time: Res<Time>, mut objective: ResMut<DeliveryObjective>) -> bool {
+    match objective.tick(time.delta_seconds()) {
+        Some(outcome) => {
+            info!(
+                "delivery objective over: {:?}, score {}",
+                outcome, objective.score
+            );
+            true
+        }
+        None => false,
+    }
+}
+
+fn spawn_delivery_hud(commands: &mut Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.0),
+                left: Val::Px(12.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn((text("", 24.0, TEXT_COLOR), DeliveryHud));
+        });
+}
+
+fn spawn_delivery_hud_system(
+    mut commands: Commands,
+    objective: Option<Res<DeliveryObjective>>,
+    hud: Query<(), With<DeliveryHud>>,
+) {
+    if objective.is_some() && hud.is_empty() {
+        spawn_delivery_hud(&mut commands);
+    }
+}
+
+fn update_delivery_hud_system(
+    objective: Res<DeliveryObjective>,
+    mut query: Query<&mut Text, With<DeliveryHud>>,
+) {
+    if !objective.is_changed() {
+        return;
+    }
+    for mut hud in query.iter_mut() {
+        hud.sections[0].value = format!(
+            "Time {:.0}s   Delivered {}/{}   Score {}",
+            objective.time_left.ceil(),
+            objective.delivered,
+            objective.required,
+            objective.score
+        );
+        hud.sections[0].style.color = match objective.outcome {
+            Some(DeliveryOutcome::TimeUp) => color("#ff8080"),
+            _ => color(TEXT_COLOR),
+        };
+    }
+}
+
+fn spawn_delivery_popup_system(
+    mut commands: Commands,
+    mut events: EventReader<PaperboxDeliveredEvent>,
+) {
+    for event in events.read() {
+        commands.spawn((
+            text(format!("+{}", event.points), 24.0, POPUP_COLOR).with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(POPUP_TOP),
+                left: Val::Px(12.0),
+                ..Default::default()
+            }),
+            DeliveryPopup(Timer::from_seconds(POPUP_SECONDS, TimerMode::Once)),
+        ));
+    }
+}
+
+fn update_delivery_popup_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut DeliveryPopup, &mut Style, &mut Text)>,
+) {
+    for (entity, mut popup, mut style, mut text) in query.iter_mut() {
+        if popup.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let progress = popup.0.percent();
+        style.top = Val::Px(POPUP_TOP - POPUP_RISE * progress);
+        text.sections[0].style.color.set_a(1.0 - progress);
+    }
+}
+
+/// Outlines the pickup and drop-off zones along their floor tracks
+fn draw_delivery_zones_system(
+    objective: Res<DeliveryObjective>,
+    layout: Res<BuildingLayout>,
+    mut gizmos: Gizmos,
+) {
+    for zone in objective.zones.iter() {
+        let Some(track) = layout.tracks.tracks().get(zone.track) else {
+            continue;
+        };
+        let zone_color = match zone.kind {
+            ZoneKind::Pickup => PICKUP_COLOR,
+            ZoneKind::DropOff => DROP_OFF_COLOR,
+        };
+        let start = track.clamp(zone.start);
+        let end = track.clamp(zone.end);
+        let steps = ((end - start) / ZONE_STEP).ceil().max(1.0) as usize;
+        gizmos.linestrip(
+            (0..=steps).map(|step| {
+                let distance = start + (end - start) * step as f32 / steps as f32;
+                track.position_at(distance)
+            }),
+            zone_color,
+        );
+        for distance in [start, end] {
+            let position = track.position_at(distance);
+            gizmos.line(position, position + Vec3::Y * PAPERBOX_SIZE, zone_color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::objective::DELIVERY_POINTS;
+    use super::*;
+    use crate::common::graph::TrackPoint;
+    use crate::game::building::level::{LevelDelivery, LevelZone};
+    use crate::game::testing::{advance, building_app, spawn_paperboxes};
+
+    fn zone(kind: ZoneKind, track: usize, start: f32, end: f32) -> LevelZone {
+        LevelZone {
+            kind,
+            track,
+            start,
+            end,
+        }
+    }
+
+    fn point(track: usize, distance: f32) -> TrackPoint {
+        TrackPoint { track, distance }
+    }
+
+    fn level_delivery(deliveries: usize) -> LevelDelivery {
+        LevelDelivery {
+            time: 60.0,
+            deliveries,
+            zones: vec![
+                zone(ZoneKind::Pickup, 0, 0.0, 20.0),
+                zone(ZoneKind::DropOff, 1, 40.0, 60.0),
+            ],
+        }
+    }
+
+    fn delivery_app(deliveries: usize) -> App {
+        let mut app = building_app(2, 100.0);
+        app.add_state::<GameState>();
+        app.add_event::<PaperboxDeliveredEvent>();
+        app.insert_resource(DeliveryObjective::new(&level_delivery(deliveries)).unwrap());
+        app.add_systems(
+            Update,
+            (
+                load_cargo_system,
+                apply_deferred,
+                deliver_paperbox_system,
+                delivery_timer_system.pipe(finished_level_system),
+            )
+                .chain()
+                .run_if(in_state(GameState::GameRunning)),
+        );
+        app.insert_resource(NextState(Some(GameState::GameRunning)));
+        app.update();
+        app
+    }
+
+    /// Picks up the paperbox, carries it to the point and drops it there
+    fn carry(app: &mut App, paperbox: Entity, to: TrackPoint) {
+        app.world.entity_mut(paperbox).insert(PaperboxCarried);
+        advance(app, 0.1);
+        let mut carried = app.world.get_mut::<Paperbox>(paperbox).unwrap();
+        carried.track = to.track;
+        carried.distance = to.distance;
+        advance(app, 0.1);
+        app.world.entity_mut(paperbox).remove::<PaperboxCarried>();
+        advance(app, 0.1);
+    }
+
+    fn delivered_points(app: &App) -> Vec<u32> {
+        let events = app.world.resource::<Events<PaperboxDeliveredEvent>>();
+        events
+            .get_reader()
+            .read(events)
+            .map(|event| event.points)
+            .collect()
+    }
+
+    fn state(app: &App) -> GameState {
+        *app.world.resource::<State<GameState>>().get()
+    }
+
+    #[test]
+    fn delivering_all_paperboxes_completes_the_level() {
+        let mut app = delivery_app(2);
+        let boxes = spawn_paperboxes(&mut app, &[point(0, 5.0), point(0, 30.0), point(0, 10.0)]);
+        advance(&mut app, 0.1);
+
+        carry(&mut app, boxes[0], point(1, 50.0));
+        assert!(app.world.get_entity(boxes[0]).is_none());
+        assert_eq!(delivered_points(&app), [DELIVERY_POINTS]);
+        assert_eq!(state(&app), GameState::GameRunning);
+
+        // only boxes from a pickup zone count
+        carry(&mut app, boxes[1], point(1, 45.0));
+        assert!(app.world.get_entity(boxes[1]).is_some());
+        assert_eq!(app.world.resource::<DeliveryObjective>().delivered, 1);
+
+        carry(&mut app, boxes[2], point(1, 55.0));
+        assert!(app.world.get_entity(boxes[2]).is_none());
+        let objective = app.world.resource::<DeliveryObjective>();
+        assert_eq!(objective.delivered, 2);
+        assert_eq!(objective.outcome, Some(DeliveryOutcome::Completed));
+        assert!(objective.score > 2 * DELIVERY_POINTS);
+        advance(&mut app, 0.1);
+        assert_eq!(state(&app), GameState::LevelComplete);
+    }
+
+    #[test]
+    fn carried_paperboxes_are_not_delivered() {
+        let mut app = delivery_app(1);
+        let paperbox = spawn_paperboxes(&mut app, &[point(0, 5.0)])[0];
+        advance(&mut app, 0.1);
+        app.world.entity_mut(paperbox).insert(PaperboxCarried);
+        app.world.get_mut::<Paperbox>(paperbox).unwrap().track = 1;
+        app.world.get_mut::<Paperbox>(paperbox).unwrap().distance = 50.0;
+        advance(&mut app, 0.1);
+        assert!(app.world.get_entity(paperbox).is_some());
+        assert_eq!(app.world.resource::<DeliveryObjective>().delivered, 0);
+    }
+
+    #[test]
+    fn restarting_the_level_resets_the_objective() {
+        let mut app = delivery_app(2);
+        let options = GameOptions::default();
+        let mut levels = Assets::<BuildingLevel>::default();
+        let level = levels.add(BuildingLevel {
+            segments: Vec::new(),
+            boxes: Vec::new(),
+            delivery: Some(level_delivery(2)),
+        });
+        let mut loader = AssetLoader::new();
+        loader.add(&options.level, level.untyped(), true);
+        app.insert_resource(options);
+        app.insert_resource(levels);
+        app.insert_resource(loader);
+        app.add_event::<LevelRestartEvent>();
+        app.add_systems(Update, reset_delivery_system);
+        let paperbox = spawn_paperboxes(&mut app, &[point(0, 5.0)])[0];
+        advance(&mut app, 0.1);
+        carry(&mut app, paperbox, point(1, 50.0));
+        assert_eq!(app.world.resource::<DeliveryObjective>().delivered, 1);
+
+        app.world.send_event(LevelRestartEvent);
+        advance(&mut app, 0.1);
+        let objective = app.world.resource::<DeliveryObjective>();
+        assert_eq!((objective.delivered, objective.score), (0, 0));
+    }
+
+    #[test]
+    fn deliveries_show_their_points_until_the_popup_expires() {
+        let mut app = building_app(1, 10.0);
+        app.add_event::<PaperboxDeliveredEvent>();
+        app.add_systems(
+            Update,
+            (spawn_delivery_popup_system, update_delivery_popup_system).chain(),
+        );
+        app.world.send_event(PaperboxDeliveredEvent { points: 100 });
+        advance(&mut app, 0.1);
+        let mut popups = app.world.query_filtered::<&Text, With<DeliveryPopup>>();
+        let labels: Vec<_> = popups
+            .iter(&app.world)
+            .map(|text| text.sections[0].value.clone())
+            .collect();
+        assert_eq!(labels, ["+100"]);
+
+        advance(&mut app, 0.1);
+        advance(&mut app, POPUP_SECONDS);
+        assert_eq!(popups.iter(&app.world).count(), 0);
+    }
+
+    #[test]
+    fn running_out_of_time_ends_the_level() {
+        let mut app = delivery_app(1);
+        advance(&mut app, 59.0);
+        assert_eq!(state(&app), GameState::GameRunning);
+        advance(&mut app, 2.0);
+        advance(&mut app, 0.1);
+        assert_eq!(state(&app), GameState::LevelComplete);
+        let objective = app.world.resource::<DeliveryObjective>();
+        assert_eq!(objective.outcome, Some(DeliveryOutcome::TimeUp));
+    }
+}
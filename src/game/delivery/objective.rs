@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+use thiserror::Error;
+
+use crate::game::building::level::{LevelDelivery, LevelZone, ZoneKind};
+
+/// Points for every delivered paperbox
+pub const DELIVERY_POINTS: u32 = 100;
+/// Points for every second left when the last paperbox is delivered
+pub const TIME_BONUS_POINTS: u32 = 10;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DeliveryError {
+    #[error("the delivery has no time budget")]
+    NoTime,
+    #[error("the delivery needs at least one paperbox to deliver")]
+    NoDeliveries,
+    #[error("the delivery has no {0:?} zone")]
+    MissingZone(ZoneKind),
+    #[error("delivery zone {0} ends before it starts")]
+    EmptyZone(usize),
+    #[error("pickup zone {pickup} overlaps drop-off zone {drop_off}")]
+    OverlappingZones { pickup: usize, drop_off: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// All paperboxes were delivered in time
+    Completed,
+    /// The time budget ran out
+    TimeUp,
+}
+
+/// Progress of the delivery objective of the level
+#[derive(Resource, Debug, Clone)]
+pub struct DeliveryObjective {
+    pub zones: Vec<LevelZone>,
+    /// Number of delivered paperboxes completing the level
+    pub required: usize,
+    pub delivered: usize,
+    pub score: u32,
+    /// Remaining time budget in seconds
+    pub time_left: f32,
+    /// Set once the objective is over
+    pub outcome: Option<DeliveryOutcome>,
+}
+
+impl DeliveryObjective {
+    pub fn new(delivery: &LevelDelivery) -> Result<Self, DeliveryError> {
+        if delivery.time <= 0.0 {
+            return Err(DeliveryError::NoTime);
+        }
+        if delivery.deliveries == 0 {
+            return Err(DeliveryError::NoDeliveries);
+        }
+        if let Some(index) = delivery.zones.iter().position(|zone| zone.end < zone.start) {
+            return Err(DeliveryError::EmptyZone(index));
+        }
+        for kind in [ZoneKind::Pickup, ZoneKind::DropOff] {
+            if !delivery.zones.iter().any(|zone| zone.kind == kind) {
+                return Err(DeliveryError::MissingZone(kind));
+            }
+        }
+        // boxes resting in both would be delivered without moving them
+        let zones = || delivery.zones.iter().enumerate();
+        for (pickup, zone) in zones().filter(|(_, zone)| zone.kind == ZoneKind::Pickup) {
+            let overlap = zones()
+                .filter(|(_, other)| other.kind == ZoneKind::DropOff)
+                .find(|(_, other)| zone.overlaps(other));
+            if let Some((drop_off, _)) = overlap {
+                return Err(DeliveryError::OverlappingZones { pickup, drop_off });
+            }
+        }
+        Ok(Self {
+            zones: delivery.zones.clone(),
+            required: delivery.deliveries,
+            delivered: 0,
+            score: 0,
+            time_left: delivery.time,
+            outcome: None,
+        })
+    }
+
+    /// Returns the index of the zone of the kind containing the track position.
+    pub fn zone_at(&self, kind: ZoneKind, track: usize, distance: f32) -> Option<usize> {
+        self.zones
+            .iter()
+            .position(|zone| zone.kind == kind && zone.contains(track, distance))
+    }
+
+    /// Counts down the time budget, returns the outcome once the objective is over.
+    pub fn tick(&mut self, seconds: f32) -> Option<DeliveryOutcome> {
+        if self.outcome.is_none() {
+            self.time_left = (self.time_left - seconds).max(0.0);
+            if self.time_left == 0.0 {
+                self.outcome = Some(DeliveryOutcome::TimeUp);
+            }
+        }
+        self.outcome
+    }
+
+    /// Counts a delivered paperbox and returns its points, the last one adds the time bonus.
+    /// Deliveries after the objective is over don't count.
+    pub fn deliver(&mut self) -> Option<u32> {
+        if self.outcome.is_some() {
+            return None;
+        }
+        self.delivered += 1;
+        let mut points = DELIVERY_POINTS;
+        if self.delivered >= self.required {
+            points += self.time_left.floor() as u32 * TIME_BONUS_POINTS;
+            self.outcome = Some(DeliveryOutcome::Completed);
+        }
+        self.score += points;
+        Some(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(kind: ZoneKind, track: usize, start: f32, end: f32) -> LevelZone {
+        LevelZone {
+            kind,
+            track,
+            start,
+            end,
+        }
+    }
+
+    fn delivery(deliveries: usize) -> LevelDelivery {
+        LevelDelivery {
+            time: 30.0,
+            deliveries,
+            zones: vec![
+                zone(ZoneKind::Pickup, 0, 0.0, 10.0),
+                zone(ZoneKind::DropOff, 1, 40.0, 50.0),
+            ],
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_deliveries() {
+        assert_eq!(
+            DeliveryObjective::new(&delivery(0)).unwrap_err(),
+            DeliveryError::NoDeliveries
+        );
+        let mut no_drop_off = delivery(1);
+        no_drop_off.zones.pop();
+        assert_eq!(
+            DeliveryObjective::new(&no_drop_off).unwrap_err(),
+            DeliveryError::MissingZone(ZoneKind::DropOff)
+        );
+        let mut empty = delivery(1);
+        empty.zones[1].end = 30.0;
+        assert_eq!(
+            DeliveryObjective::new(&empty).unwrap_err(),
+            DeliveryError::EmptyZone(1)
+        );
+        let mut overlapping = delivery(1);
+        overlapping
+            .zones
+            .push(zone(ZoneKind::DropOff, 0, 10.0, 20.0));
+        assert_eq!(
+            DeliveryObjective::new(&overlapping).unwrap_err(),
+            DeliveryError::OverlappingZones {
+                pickup: 0,
+                drop_off: 2
+            }
+        );
+        // the same stretch on another floor is fine
+        overlapping.zones[2].track = 1;
+        assert!(DeliveryObjective::new(&overlapping).is_ok());
+    }
+
+    #[test]
+    fn finds_zones_by_kind() {
+        let objective = DeliveryObjective::new(&delivery(1)).unwrap();
+        assert_eq!(objective.zone_at(ZoneKind::Pickup, 0, 5.0), Some(0));
+        assert_eq!(objective.zone_at(ZoneKind::DropOff, 0, 5.0), None);
+        assert_eq!(objective.zone_at(ZoneKind::DropOff, 1, 45.0), Some(1));
+        assert_eq!(objective.zone_at(ZoneKind::DropOff, 0, 45.0), None);
+    }
+
+    #[test]
+    fn completes_with_time_bonus() {
+        let mut objective = DeliveryObjective::new(&delivery(2)).unwrap();
+        assert_eq!(objective.tick(10.5), None);
+        assert_eq!(objective.deliver(), Some(DELIVERY_POINTS));
+        assert_eq!(objective.outcome, None);
+        assert_eq!(
+            objective.deliver(),
+            Some(DELIVERY_POINTS + 19 * TIME_BONUS_POINTS)
+        );
+        assert_eq!(objective.outcome, Some(DeliveryOutcome::Completed));
+        assert_eq!(
+            objective.score,
+            2 * DELIVERY_POINTS + 19 * TIME_BONUS_POINTS
+        );
+        // the clock stops once completed
+        assert_eq!(objective.tick(100.0), Some(DeliveryOutcome::Completed));
+        assert_eq!(objective.deliver(), None);
+    }
+
+    #[test]
+    fn runs_out_of_time() {
+        let mut objective = DeliveryObjective::new(&delivery(1)).unwrap();
+        assert_eq!(objective.tick(29.0), None);
+        assert_eq!(objective.tick(2.0), Some(DeliveryOutcome::TimeUp));
+        assert_eq!(objective.time_left, 0.0);
+        assert_eq!(objective.deliver(), None);
+        assert_eq!(objective.score, 0);
+    }
+}
@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+
+use super::building::LevelRestartEvent;
+use super::delivery::objective::{DeliveryObjective, DeliveryOutcome};
+use super::states::{replay_level_system, GameState};
+use super::ui::{
+    color, despawn_screen_system, spawn_button, spawn_overlay, text, update_button, ButtonColors,
+    TEXT_COLOR,
+};
+
+pub struct LevelCompletePlugin;
+
+impl Plugin for LevelCompletePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::LevelComplete), spawn_result_panel_system);
+        app.add_systems(OnExit(GameState::LevelComplete), despawn_screen_system);
+        app.add_systems(
+            Update,
+            replay_input_system
+                .pipe(restart_level_system)
+                .pipe(replay_level_system)
+                .run_if(in_state(GameState::LevelComplete)),
+        );
+    }
+}
+
+const PANEL_COLOR: &str = "#0f1f2b";
+const FAILED_COLOR: &str = "#ff8080";
+const BUTTON_COLORS: ButtonColors = ButtonColors {
+    normal: "#20405a",
+    hovered: "#30607a",
+};
+const REPLAY_KEY: KeyCode = KeyCode::R;
+
+#[derive(Component)]
+struct ReplayButton;
+
+fn spawn_result_panel_system(mut commands: Commands, objective: Option<Res<DeliveryObjective>>) {
+    let Some(objective) = objective else {
+        return;
+    };
+    let (title, title_color) = match objective.outcome {
+        Some(DeliveryOutcome::TimeUp) => ("Time is up", FAILED_COLOR),
+        _ => ("Level complete", TEXT_COLOR),
+    };
+    spawn_overlay(&mut commands, |parent| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(8.0),
+                    padding: UiRect::all(Val::Px(24.0)),
+                    ..Default::default()
+                },
+                background_color: color(PANEL_COLOR).into(),
+                ..Default::default()
+            })
+            .with_children(|panel| {
+                panel.spawn(text(title, 32.0, title_color));
+                panel.spawn(text(
+                    format!("Delivered {}/{}", objective.delivered, objective.required),
+                    20.0,
+                    TEXT_COLOR,
+                ));
+                panel.spawn(text(format!("Score {}", objective.score), 20.0, TEXT_COLOR));
+                spawn_button(
+                    panel,
+                    format!("Play again ({:?})", REPLAY_KEY),
+                    BUTTON_COLORS,
+                    ReplayButton,
+                );
+            });
+    });
+}
+
+/// Returns true if the replay button was pressed or the replay key was pressed
+fn replay_input_system(
+    mut buttons: Query<(&Interaction, &mut BackgroundColor, &ButtonColors), With<ReplayButton>>,
+    input: Res<Input<KeyCode>>,
+) -> bool {
+    let mut replay = input.just_pressed(REPLAY_KEY);
+    for (interaction, mut background, colors) in buttons.iter_mut() {
+        replay |= update_button(interaction, &mut background, colors);
+    }
+    replay
+}
+
+/// Restarts the level, which respawns the paperboxes, moves paperman back to his start and
+/// restarts the delivery objective
+fn restart_level_system(In(replay): In<bool>, mut events: EventWriter<LevelRestartEvent>) -> bool {
+    if replay {
+        events.send(LevelRestartEvent);
+    }
+    replay
+}
@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+
+use crate::common::loader::AssetLoader;
+
+use super::states::{retry_loading_system, GameState};
+use super::ui::{
+    color, despawn_screen_system, spawn_button, spawn_screen, text, update_button, ButtonColors,
+    TEXT_COLOR,
+};
+
+pub struct LoadFailedPlugin;
+
+impl Plugin for LoadFailedPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::LoadFailed), spawn_error_panel_system);
+        app.add_systems(OnExit(GameState::LoadFailed), despawn_screen_system);
+        app.add_systems(
+            Update,
+            retry_input_system
+                .pipe(retry_system)
+                .pipe(retry_loading_system)
+                .run_if(in_state(GameState::LoadFailed)),
+        );
+    }
+}
+
+const PANEL_COLOR: &str = "#2b0f0f";
+const ERROR_COLOR: &str = "#ff8080";
+const BUTTON_COLORS: ButtonColors = ButtonColors {
+    normal: "#5a2020",
+    hovered: "#7a3030",
+};
+const RETRY_KEY: KeyCode = KeyCode::R;
+
+#[derive(Component)]
+struct RetryButton;
+
+fn spawn_error_panel_system(
+    mut commands: Commands,
+    loader: Res<AssetLoader>,
+    server: Res<AssetServer>,
+) {
+    spawn_screen(&mut commands, |parent| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(8.0),
+                    padding: UiRect::all(Val::Px(24.0)),
+                    max_width: Val::Percent(80.0),
+                    ..Default::default()
+                },
+                background_color: color(PANEL_COLOR).into(),
+                ..Default::default()
+            })
+            .with_children(|panel| {
+                panel.spawn(text("Failed loading assets", 32.0, TEXT_COLOR));
+                for (path, reason) in loader.failures(&server) {
+                    panel.spawn(text(path, 20.0, TEXT_COLOR));
+                    panel.spawn(text(reason, 16.0, ERROR_COLOR));
+                }
+                spawn_button(
+                    panel,
+                    format!("Retry ({:?})", RETRY_KEY),
+                    BUTTON_COLORS,
+                    RetryButton,
+                );
+            });
+    });
+}
+
+/// Returns true if the retry button was pressed or the retry key was pressed
+fn retry_input_system(
+    mut buttons: Query<(&Interaction, &mut BackgroundColor, &ButtonColors), With<RetryButton>>,
+    input: Res<Input<KeyCode>>,
+) -> bool {
+    let mut retry = input.just_pressed(RETRY_KEY);
+    for (interaction, mut background, colors) in buttons.iter_mut() {
+        retry |= update_button(interaction, &mut background, colors);
+    }
+    retry
+}
+
+/// Resubmits the failed assets through the loader
+fn retry_system(
+    In(retry): In<bool>,
+    mut loader: ResMut<AssetLoader>,
+    server: Res<AssetServer>,
+) -> bool {
+    if retry {
+        loader.retry_failed(&server);
+    }
+    retry
+}
@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+
+use crate::common::loader::{AssetLoader, AssetLoadingProgress, AssetLoadingState};
+
+use super::states::GameState;
+use super::ui::{color, despawn_screen_system, spawn_screen, text, TEXT_COLOR};
+
+pub struct LoadingScreenPlugin;
+
+impl Plugin for LoadingScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::AssetsLoading),
+            spawn_loading_screen_system,
+        );
+        app.add_systems(OnExit(GameState::AssetsLoading), despawn_screen_system);
+        app.add_systems(
+            Update,
+            update_loading_screen_system.run_if(in_state(GameState::AssetsLoading)),
+        );
+    }
+}
+
+const BAR_COLOR: &str = "#f0f0f0";
+const BAR_BACKGROUND_COLOR: &str = "#303030";
+const ASSET_COLOR: &str = "#a0a0a0";
+
+#[derive(Component)]
+struct ProgressBar;
+
+#[derive(Component)]
+struct ProgressLabel;
+
+#[derive(Component)]
+struct AssetList;
+
+fn progress_label(progress: &AssetLoadingProgress) -> String {
+    format!(
+        "Loading {}/{} ({:.0}%)",
+        progress.loaded,
+        progress.total(),
+        progress.fraction() * 100.0
+    )
+}
+
+fn state_label(state: &AssetLoadingState) -> &'static str {
+    match state {
+        AssetLoadingState::Queued => "queued",
+        AssetLoadingState::Loading => "loading",
+        AssetLoadingState::Loaded => "loaded",
+        AssetLoadingState::Failed(_) => "failed",
+    }
+}
+
+fn spawn_asset_list(list: &mut ChildBuilder, loader: &AssetLoader, server: &AssetServer) {
+    for (path, state) in loader.states(server) {
+        list.spawn(text(
+            format!("{} ({})", path, state_label(&state)),
+            14.0,
+            ASSET_COLOR,
+        ));
+    }
+}
+
+fn spawn_loading_screen_system(
+    mut commands: Commands,
+    loader: Res<AssetLoader>,
+    server: Res<AssetServer>,
+) {
+    let progress = loader.progress();
+    spawn_screen(&mut commands, |parent| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(8.0),
+                    width: Val::Percent(50.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with_children(|column| {
+                column.spawn((
+                    text(progress_label(&progress), 24.0, TEXT_COLOR),
+                    ProgressLabel,
+                ));
+                column
+                    .spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Px(12.0),
+                            ..Default::default()
+                        },
+                        background_color: color(BAR_BACKGROUND_COLOR).into(),
+                        ..Default::default()
+                    })
+                    .with_children(|bar| {
+                        bar.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Percent(progress.fraction() * 100.0),
+                                    height: Val::Percent(100.0),
+                                    ..Default::default()
+                                },
+                                background_color: color(BAR_COLOR).into(),
+                                ..Default::default()
+                            },
+                            ProgressBar,
+                        ));
+                    });
+                column
+                    .spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        AssetList,
+                    ))
+                    .with_children(|list| spawn_asset_list(list, &loader, &server));
+            });
+    });
+}
+
+/// Updates the progress bar, label and the state of every asset when the progress changed
+fn update_loading_screen_system(
+    mut commands: Commands,
+    mut events: EventReader<AssetLoadingProgress>,
+    mut bar: Query<&mut Style, With<ProgressBar>>,
+    mut label: Query<&mut Text, With<ProgressLabel>>,
+    list: Query<Entity, With<AssetList>>,
+    loader: Res<AssetLoader>,
+    server: Res<AssetServer>,
+) {
+    let Some(progress) = events.read().last() else {
+        return;
+    };
+    for mut style in bar.iter_mut() {
+        style.width = Val::Percent(progress.fraction() * 100.0);
+    }
+    for mut text in label.iter_mut() {
+        text.sections[0].value = progress_label(progress);
+    }
+    for list in list.iter() {
+        commands
+            .entity(list)
+            .despawn_descendants()
+            .with_children(|list| spawn_asset_list(list, &loader, &server));
+    }
+}
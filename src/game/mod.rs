@@ -5,22 +5,35 @@ use self::states::{finished_game_loading_system, GameState};
 mod assets;
 mod building;
 mod camera;
+mod delivery;
+mod level_complete;
+mod load_failed;
+mod loading_screen;
+mod options;
 mod paperbox;
 mod paperman;
 mod render;
 mod states;
+#[cfg(test)]
+mod testing;
+mod ui;
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_state::<states::GameState>();
+        app.insert_resource(options::GameOptions::from_args(std::env::args().skip(1)));
         app.add_plugins((
             assets::GameAssetPlugin,
             building::BuildingPlugin,
             paperman::PapermanPlugin,
             paperbox::PaperboxPlugin,
+            delivery::DeliveryPlugin,
             camera::CameraPlugin,
+            level_complete::LevelCompletePlugin,
+            load_failed::LoadFailedPlugin,
+            loading_screen::LoadingScreenPlugin,
             render::RenderPlugin,
         ));
         app.add_systems(
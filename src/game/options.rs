@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+
+use super::assets::LEVEL;
+use super::building::BuildingMode;
+
+/// Options picked at startup from the command line arguments
+#[derive(Resource, Debug, Clone)]
+pub struct GameOptions {
+    pub building_mode: BuildingMode,
+    /// Manifest name of the level laid out in fixed mode
+    pub level: String,
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        Self {
+            building_mode: BuildingMode::default(),
+            level: LEVEL.to_string(),
+        }
+    }
+}
+
+impl GameOptions {
+    /// Parses the arguments, without the program name:
+    ///
+    /// --endless       endlessly generate building segments
+    /// --level <name>  lay out the building from the named manifest level
+    /// --seed <seed>   generate the building from the seed
+    /// --generate      generate the building from a random seed
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut options = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--endless" => options.building_mode = BuildingMode::Endless,
+                "--level" => match args.next() {
+                    Some(level) => options.level = level,
+                    None => warn!("Missing level name after --level"),
+                },
+                "--seed" => match args.next().map(|seed| seed.parse()) {
+                    Some(Ok(seed)) => options.building_mode = BuildingMode::Generated(seed),
+                    _ => warn!("Expected a number after --seed"),
+                },
+                "--generate" => {
+                    let seed = random_seed();
+                    info!(
+                        "Picked building seed {}, reproduce with --seed {}",
+                        seed, seed
+                    );
+                    options.building_mode = BuildingMode::Generated(seed);
+                }
+                _ => warn!("Unknown argument: {}", arg),
+            }
+        }
+        options
+    }
+}
+
+/// Returns a seed that is different on every start
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
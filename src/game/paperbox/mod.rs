@@ -1,30 +1,362 @@
 use bevy::prelude::*;
 
-// use super::{
-//     assets::{BuildingResource, PaperboxResource},
-//     states::GameState,
-// };
+use crate::common::graph::TrackPoint;
+use crate::common::loader::AssetLoader;
+use crate::common::track::Track;
+
+use self::physics::PhysicsBox;
+pub use self::physics::PhysicsParams;
+
+use super::{
+    assets::PaperboxResource,
+    building::{
+        level::BuildingLevel,
+        marker::{BuildingMarker, MarkerKind},
+        prepare_scenes_system, respawn_scenes_system, selected_level, stream_endless_scenes_system,
+        BuildingLayout, BuildingMode, BuildingRespawnedEvent, BuildingTrimmedEvent,
+        LevelRestartEvent,
+    },
+    options::GameOptions,
+    paperman::{Paperman, PapermanPosition},
+    states::GameState,
+};
+
+mod physics;
 
 pub struct PaperboxPlugin;
 
 impl Plugin for PaperboxPlugin {
-    fn build(&self, _app: &mut App) {
-        // app.add_systems(OnEnter(GameState::GameLoading), prepare_paperbox_system);
-        // app.add_systems(
-        //     Update,
-        //     debug_paperbox_system.run_if(in_state(GameState::GameRunning)),
-        // );
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PaperboxDebug>();
+        app.init_resource::<PhysicsParams>();
+        app.add_systems(
+            FixedUpdate,
+            paperbox_physics_system.run_if(in_state(GameState::GameRunning)),
+        );
+        app.add_systems(
+            OnEnter(GameState::GameLoading),
+            prepare_paperbox_system.after(prepare_scenes_system),
+        );
+        app.add_systems(
+            Update,
+            (
+                respawn_paperbox_system.after(respawn_scenes_system),
+                trim_paperbox_system.after(stream_endless_scenes_system),
+                spawn_marker_paperbox_system,
+                update_paperbox_transform_system,
+                toggle_paperbox_debug_system,
+                debug_paperbox_system,
+            )
+                .chain()
+                .run_if(in_state(GameState::GameRunning)),
+        );
+    }
+}
+
+/// Size of the paperbox model, it stands on its origin
+pub const PAPERBOX_SIZE: f32 = 2.0;
+
+/// Toggles the paperbox debug view
+const TOGGLE_DEBUG_KEY: KeyCode = KeyCode::F4;
+
+/// A paperbox standing on one of the floor tracks
+#[derive(Component, Debug)]
+pub struct Paperbox {
+    /// Index of the floor track in the building layout
+    pub track: usize,
+    /// Distance along the floor track
+    pub distance: f32,
+}
+
+/// Movement of a paperbox along its track, see [`physics::step`]
+#[derive(Component, Debug, Default, Clone, PartialEq)]
+pub struct PaperboxMotion {
+    /// Velocity along the track, positive towards the end of the track
+    pub velocity: f32,
+    /// Height above the track
+    pub height: f32,
+    /// Upwards velocity
+    pub vertical_velocity: f32,
+}
+
+impl PaperboxMotion {
+    /// Returns true if the paperbox stands still, on the floor or on another paperbox
+    pub fn is_resting(&self) -> bool {
+        self.velocity == 0.0 && self.vertical_velocity == 0.0
+    }
+
+    /// Adds an impulse along the track and upwards
+    pub fn push(&mut self, velocity: f32, vertical_velocity: f32) {
+        self.velocity += velocity;
+        self.vertical_velocity += vertical_velocity;
     }
 }
 
+/// A paperbox carried by paperman, attached to his hand instead of standing on its track
 #[derive(Component, Debug)]
-pub struct Paperbox;
+pub struct PaperboxCarried;
+
+/// Draws the paperbox bounds and track positions with gizmos
+#[derive(Resource, Debug, Default)]
+pub struct PaperboxDebug {
+    pub enabled: bool,
+}
+
+pub fn spawn_paperbox(
+    commands: &mut Commands,
+    paperbox: &PaperboxResource,
+    layout: &BuildingLayout,
+    point: TrackPoint,
+) {
+    let Some(track) = layout.tracks.tracks().get(point.track) else {
+        warn!("Skipping paperbox on missing floor track {}", point.track);
+        return;
+    };
+    let distance = track.clamp(point.distance);
+    commands.spawn((
+        Paperbox {
+            track: point.track,
+            distance,
+        },
+        PaperboxMotion::default(),
+        SceneBundle {
+            scene: paperbox.scene.clone(),
+            transform: Transform::from_translation(track.position_at(distance)),
+            ..Default::default()
+        },
+    ));
+}
+
+/// Spawns the paperboxes of the level and the ones placed by the generator, boxes placed by
+/// markers are spawned with the markers
+fn spawn_level_paperboxes(
+    commands: &mut Commands,
+    paperbox: &PaperboxResource,
+    layout: &BuildingLayout,
+    level: Option<&BuildingLevel>,
+) {
+    let level_boxes = level
+        .iter()
+        .flat_map(|level| level.boxes.iter())
+        .map(|level_box| TrackPoint {
+            track: level_box.track,
+            distance: level_box.distance,
+        });
+    for point in level_boxes.chain(layout.boxes.iter().copied()) {
+        spawn_paperbox(commands, paperbox, layout, point);
+    }
+}
 
-// pub fn prepare_paperbox_system(
-//     mut commands: Commands,
-//     paperbox: Res<PaperboxResource>,
-//     building: Res<BuildingResource>,
-// ) {
-// }
+/// Returns the selected level, only the fixed building is laid out from the level
+fn fixed_level<'a>(
+    options: &GameOptions,
+    loader: &AssetLoader,
+    levels: &'a Assets<BuildingLevel>,
+) -> Option<&'a BuildingLevel> {
+    if options.building_mode != BuildingMode::Fixed {
+        return None;
+    }
+    selected_level(options, loader, levels)
+}
 
-// pub fn debug_paperbox_system(query: Query<(&Transform), With<Paperbox>>, mut gizmos: Gizmos) {}
+fn prepare_paperbox_system(
+    mut commands: Commands,
+    paperbox: Res<PaperboxResource>,
+    layout: Res<BuildingLayout>,
+    options: Res<GameOptions>,
+    loader: Res<AssetLoader>,
+    levels: Res<Assets<BuildingLevel>>,
+) {
+    let level = fixed_level(&options, &loader, &levels);
+    spawn_level_paperboxes(&mut commands, &paperbox, &layout, level);
+}
+
+/// Replaces the paperboxes after the building was respawned or the level restarted
+#[allow(clippy::too_many_arguments)]
+fn respawn_paperbox_system(
+    mut commands: Commands,
+    mut events: EventReader<BuildingRespawnedEvent>,
+    mut restart_events: EventReader<LevelRestartEvent>,
+    query: Query<Entity, With<Paperbox>>,
+    paperbox: Res<PaperboxResource>,
+    layout: Res<BuildingLayout>,
+    options: Res<GameOptions>,
+    loader: Res<AssetLoader>,
+    levels: Res<Assets<BuildingLevel>>,
+) {
+    let respawned = events.read().last().is_some();
+    let restarted = restart_events.read().last().is_some();
+    if !respawned && !restarted {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let level = fixed_level(&options, &loader, &levels);
+    spawn_level_paperboxes(&mut commands, &paperbox, &layout, level);
+}
+
+/// Moves the paperboxes back with the tracks trimmed behind paperman, the ones left on the
+/// removed part are despawned with the segments they were placed in
+fn trim_paperbox_system(
+    mut commands: Commands,
+    mut events: EventReader<BuildingTrimmedEvent>,
+    mut query: Query<(Entity, &mut Paperbox, Has<PaperboxCarried>)>,
+) {
+    for event in events.read() {
+        for (entity, mut paperbox, carried) in query.iter_mut() {
+            match event.shifted(paperbox.track, paperbox.distance) {
+                Some(distance) => paperbox.distance = distance,
+                // the carried paperbox moves with paperman
+                None if carried => paperbox.distance = 0.0,
+                None => commands.entity(entity).despawn_recursive(),
+            }
+        }
+    }
+}
+
+/// Spawns a paperbox on the closest floor track of every new `spawn_box` marker
+fn spawn_marker_paperbox_system(
+    mut commands: Commands,
+    markers: Query<(&BuildingMarker, &GlobalTransform), Added<BuildingMarker>>,
+    paperbox: Res<PaperboxResource>,
+    layout: Res<BuildingLayout>,
+) {
+    for (marker, transform) in markers.iter() {
+        if marker.kind != MarkerKind::SpawnBox {
+            continue;
+        }
+        if let Some(point) = layout.tracks.closest_point(transform.translation()) {
+            spawn_paperbox(&mut commands, &paperbox, &layout, point);
+        }
+    }
+}
+
+/// Steps the physics of all paperboxes that are not carried, see [`physics::step`]
+pub fn paperbox_physics_system(
+    time: Res<Time>,
+    params: Res<PhysicsParams>,
+    layout: Res<BuildingLayout>,
+    paperman: Query<&PapermanPosition, With<Paperman>>,
+    mut query: Query<(&mut Paperbox, &mut PaperboxMotion), Without<PaperboxCarried>>,
+) {
+    let mut boxes: Vec<PhysicsBox> = query
+        .iter()
+        .map(|(paperbox, motion)| PhysicsBox {
+            track: paperbox.track,
+            distance: paperbox.distance,
+            motion: motion.clone(),
+        })
+        .collect();
+    if boxes.is_empty() {
+        return;
+    }
+    let track_lengths: Vec<f32> = layout.tracks.tracks().iter().map(Track::length).collect();
+    let paperman = paperman.get_single().ok().map(|position| TrackPoint {
+        track: position.track,
+        distance: position.distance,
+    });
+    physics::step(
+        &mut boxes,
+        &track_lengths,
+        paperman,
+        &params,
+        time.delta_seconds(),
+    );
+    // only touch the boxes that moved to keep change detection useful
+    for ((mut paperbox, mut motion), stepped) in query.iter_mut().zip(boxes) {
+        if paperbox.distance != stepped.distance {
+            paperbox.distance = stepped.distance;
+        }
+        if *motion != stepped.motion {
+            *motion = stepped.motion;
+        }
+    }
+}
+
+/// Places the paperboxes on their tracks
+#[allow(clippy::type_complexity)]
+fn update_paperbox_transform_system(
+    layout: Res<BuildingLayout>,
+    mut query: Query<
+        (&Paperbox, &PaperboxMotion, &mut Transform),
+        (
+            Or<(Changed<Paperbox>, Changed<PaperboxMotion>)>,
+            Without<PaperboxCarried>,
+        ),
+    >,
+) {
+    for (paperbox, motion, mut transform) in query.iter_mut() {
+        if let Some(track) = layout.tracks.tracks().get(paperbox.track) {
+            transform.translation = track.position_at(paperbox.distance) + Vec3::Y * motion.height;
+        }
+    }
+}
+
+fn toggle_paperbox_debug_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut debug_view: ResMut<PaperboxDebug>,
+) {
+    if keyboard_input.just_pressed(TOGGLE_DEBUG_KEY) {
+        debug_view.enabled = !debug_view.enabled;
+        info!("paperbox debug view enabled = {}", debug_view.enabled);
+    }
+}
+
+/// Draws the bounds of every paperbox and the tangent of its track
+fn debug_paperbox_system(
+    debug: Res<PaperboxDebug>,
+    layout: Res<BuildingLayout>,
+    query: Query<(&Paperbox, &Transform), Without<PaperboxCarried>>,
+    mut gizmos: Gizmos,
+) {
+    if !debug.enabled {
+        return;
+    }
+    for (paperbox, transform) in query.iter() {
+        let center = transform.translation + Vec3::Y * PAPERBOX_SIZE / 2.0;
+        gizmos.cuboid(
+            Transform::from_translation(center).with_scale(Vec3::splat(PAPERBOX_SIZE)),
+            Color::YELLOW,
+        );
+        if let Some(track) = layout.tracks.tracks().get(paperbox.track) {
+            let position = track.position_at(paperbox.distance);
+            gizmos.ray(position, track.tangent_at(paperbox.distance), Color::GREEN);
+            gizmos.line(position, center, Color::RED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::testing::{building_app, spawn_paperboxes};
+
+    #[test]
+    fn trimming_despawns_the_paperboxes_behind() {
+        let mut app = building_app(2, 100.0);
+        app.add_event::<BuildingTrimmedEvent>();
+        app.add_systems(Update, trim_paperbox_system);
+        let point = |track, distance| TrackPoint { track, distance };
+        let boxes = spawn_paperboxes(
+            &mut app,
+            &[
+                point(0, 10.0),
+                point(0, 50.0),
+                point(1, 50.0),
+                point(1, 10.0),
+            ],
+        );
+        app.world.entity_mut(boxes[3]).insert(PaperboxCarried);
+
+        app.world.send_event(BuildingTrimmedEvent {
+            removed: vec![30.0, 60.0],
+        });
+        app.update();
+
+        assert!(app.world.get_entity(boxes[0]).is_none());
+        assert_eq!(app.world.get::<Paperbox>(boxes[1]).unwrap().distance, 20.0);
+        assert!(app.world.get_entity(boxes[2]).is_none());
+        assert_eq!(app.world.get::<Paperbox>(boxes[3]).unwrap().distance, 0.0);
+    }
+}
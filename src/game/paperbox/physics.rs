@@ -0,0 +1,339 @@
+use bevy::prelude::*;
+
+use crate::common::graph::TrackPoint;
+
+use super::{PaperboxMotion, PAPERBOX_SIZE};
+
+/// Constants of the paperbox physics step.
+#[derive(Resource, Debug, Clone)]
+pub struct PhysicsParams {
+    /// Downwards acceleration of falling paperboxes
+    pub gravity: f32,
+    /// Deceleration of paperboxes sliding on the floor or on another paperbox
+    pub friction: f32,
+    /// Width and height of a paperbox
+    pub size: f32,
+    /// Half of paperman's width along the track
+    pub paperman_radius: f32,
+    /// Paperboxes flying higher than this pass over paperman
+    pub paperman_height: f32,
+}
+
+impl Default for PhysicsParams {
+    fn default() -> Self {
+        Self {
+            gravity: 20.0,
+            friction: 12.0,
+            size: PAPERBOX_SIZE,
+            paperman_radius: 0.8,
+            paperman_height: 3.5,
+        }
+    }
+}
+
+/// A paperbox as seen by the physics step, the distance is the center of the box.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhysicsBox {
+    pub track: usize,
+    pub distance: f32,
+    pub motion: PaperboxMotion,
+}
+
+/// Tolerance for resting contacts
+const CONTACT_EPSILON: f32 = 1e-3;
+
+impl PhysicsBox {
+    fn overlaps_horizontally(&self, other: &PhysicsBox, size: f32) -> bool {
+        self.track == other.track && (self.distance - other.distance).abs() < size - CONTACT_EPSILON
+    }
+
+    fn overlaps_vertically(&self, other: &PhysicsBox, size: f32) -> bool {
+        (self.motion.height - other.motion.height).abs() < size - CONTACT_EPSILON
+    }
+}
+
+/// Returns the height the box rests at, the top of the highest box below it or the floor.
+fn support_height(boxes: &[PhysicsBox], index: usize, size: f32) -> f32 {
+    let this = &boxes[index];
+    boxes
+        .iter()
+        .enumerate()
+        .filter(|(other, _)| *other != index)
+        .map(|(_, other)| other)
+        .filter(|other| this.overlaps_horizontally(other, size))
+        .map(|other| other.motion.height + size)
+        .filter(|top| *top <= this.motion.height + CONTACT_EPSILON)
+        .fold(0.0, f32::max)
+}
+
+/// Advances the paperboxes by `dt` along their tracks: gravity onto the floor or the boxes
+/// below, friction while sliding, stopping at the track ends and at paperman, and elastic
+/// collisions between boxes.
+///
+/// Boxes are processed from the bottom up in a fixed order, the result only depends on the
+/// input so the same steps always give the same result.
+pub fn step(
+    boxes: &mut [PhysicsBox],
+    track_lengths: &[f32],
+    paperman: Option<TrackPoint>,
+    params: &PhysicsParams,
+    dt: f32,
+) {
+    let size = params.size;
+    let mut order: Vec<usize> = (0..boxes.len()).collect();
+    order.sort_by(|a, b| {
+        let (a_box, b_box) = (&boxes[*a], &boxes[*b]);
+        a_box
+            .motion
+            .height
+            .total_cmp(&b_box.motion.height)
+            .then(a.cmp(b))
+    });
+
+    for index in order {
+        let support = support_height(boxes, index, size);
+        let this = &mut boxes[index];
+        let Some(length) = track_lengths.get(this.track).copied() else {
+            continue;
+        };
+        let previous = this.distance;
+
+        // sliding:
+        if this.motion.height <= support + CONTACT_EPSILON {
+            let speed = (this.motion.velocity.abs() - params.friction * dt).max(0.0);
+            this.motion.velocity = speed.copysign(this.motion.velocity);
+        }
+        this.distance += this.motion.velocity * dt;
+
+        // track ends:
+        let clamped = this.distance.clamp(0.0, length);
+        if clamped != this.distance {
+            this.distance = clamped;
+            this.motion.velocity = 0.0;
+        }
+
+        // paperman:
+        if let Some(paperman) = paperman.filter(|paperman| paperman.track == this.track) {
+            let reach = params.paperman_radius + size / 2.0;
+            let offset = this.distance - paperman.distance;
+            // boxes thrown or dropped by paperman start within his reach, let them leave it
+            let leaving = this.motion.velocity * offset > 0.0;
+            if offset.abs() < reach && this.motion.height < params.paperman_height && !leaving {
+                let side = if previous != paperman.distance {
+                    (previous - paperman.distance).signum()
+                } else {
+                    1.0
+                };
+                this.distance = (paperman.distance + side * reach).clamp(0.0, length);
+                this.motion.velocity = 0.0;
+            }
+        }
+
+        // other boxes:
+        for other in 0..boxes.len() {
+            if other == index {
+                continue;
+            }
+            let (this, that) = pair_mut(boxes, index, other);
+            if !this.overlaps_horizontally(that, size) || !this.overlaps_vertically(that, size) {
+                continue;
+            }
+            let side = if previous != that.distance {
+                (previous - that.distance).signum()
+            } else {
+                1.0
+            };
+            this.distance = (that.distance + side * size).clamp(0.0, length);
+            // only swap when moving towards each other
+            if (this.motion.velocity - that.motion.velocity) * side < 0.0 {
+                std::mem::swap(&mut this.motion.velocity, &mut that.motion.velocity);
+            }
+        }
+
+        // gravity, the support might have moved away:
+        let support = support_height(boxes, index, size);
+        let this = &mut boxes[index];
+        this.motion.vertical_velocity -= params.gravity * dt;
+        this.motion.height += this.motion.vertical_velocity * dt;
+        if this.motion.height <= support {
+            this.motion.height = support;
+            this.motion.vertical_velocity = 0.0;
+        }
+    }
+}
+
+/// Returns mutable references to two different elements.
+fn pair_mut<T>(items: &mut [T], a: usize, b: usize) -> (&mut T, &mut T) {
+    assert_ne!(a, b);
+    if a < b {
+        let (left, right) = items.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = items.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 64.0;
+
+    fn physics_box(distance: f32, height: f32) -> PhysicsBox {
+        PhysicsBox {
+            track: 0,
+            distance,
+            motion: PaperboxMotion {
+                height,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn simulate(boxes: &mut [PhysicsBox], paperman: Option<TrackPoint>, steps: usize) {
+        let params = PhysicsParams::default();
+        for _ in 0..steps {
+            step(boxes, &[100.0], paperman, &params, DT);
+        }
+    }
+
+    #[test]
+    fn falls_onto_the_floor() {
+        let mut boxes = [physics_box(10.0, 5.0)];
+        simulate(&mut boxes, None, 128);
+        assert_eq!(boxes[0].motion.height, 0.0);
+        assert_eq!(boxes[0].motion.vertical_velocity, 0.0);
+        assert_eq!(boxes[0].distance, 10.0);
+    }
+
+    #[test]
+    fn stacks_and_rests() {
+        let size = PhysicsParams::default().size;
+        let mut boxes = [
+            physics_box(10.3, 9.0),
+            physics_box(10.0, 0.0),
+            physics_box(9.8, 4.0),
+        ];
+        simulate(&mut boxes, None, 256);
+        let mut heights: Vec<f32> = boxes.iter().map(|b| b.motion.height).collect();
+        heights.sort_by(f32::total_cmp);
+        assert_eq!(heights, [0.0, size, 2.0 * size]);
+
+        // resting stacks don't drift
+        let rested = boxes.clone();
+        simulate(&mut boxes, None, 256);
+        assert_eq!(boxes, rested);
+    }
+
+    #[test]
+    fn falls_when_the_support_slides_away() {
+        let size = PhysicsParams::default().size;
+        let mut boxes = [physics_box(10.0, 0.0), physics_box(10.0, size)];
+        simulate(&mut boxes, None, 8);
+        assert_eq!(boxes[1].motion.height, size);
+        boxes[0].motion.velocity = 20.0;
+        simulate(&mut boxes, None, 128);
+        assert_eq!(boxes[1].motion.height, 0.0);
+        assert!(boxes[0].distance > boxes[1].distance + size);
+    }
+
+    #[test]
+    fn sliding_stops_with_friction() {
+        let params = PhysicsParams::default();
+        let mut boxes = [physics_box(10.0, 0.0)];
+        boxes[0].motion.velocity = 12.0;
+        simulate(&mut boxes, None, 256);
+        assert_eq!(boxes[0].motion.velocity, 0.0);
+        // v^2 / 2a, a bit less with the discrete steps
+        let expected = 12.0 * 12.0 / (2.0 * params.friction);
+        assert!((boxes[0].distance - 10.0 - expected).abs() < 0.2);
+    }
+
+    #[test]
+    fn sliding_is_deterministic() {
+        let start = || {
+            let mut boxes = [
+                physics_box(10.0, 0.0),
+                physics_box(14.0, 0.0),
+                physics_box(20.0, 3.0),
+            ];
+            boxes[0].motion.velocity = 15.0;
+            boxes[2].motion.velocity = -4.0;
+            boxes
+        };
+        let (mut a, mut b) = (start(), start());
+        simulate(&mut a, None, 300);
+        simulate(&mut b, None, 300);
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert_eq!(a.distance.to_bits(), b.distance.to_bits());
+            assert_eq!(a.motion.height.to_bits(), b.motion.height.to_bits());
+        }
+    }
+
+    #[test]
+    fn collisions_pass_the_velocity_on() {
+        let mut boxes = [physics_box(10.0, 0.0), physics_box(13.0, 0.0)];
+        boxes[0].motion.velocity = 10.0;
+        simulate(&mut boxes, None, 16);
+        assert!(boxes[1].motion.velocity > 0.0);
+        assert!(boxes[1].distance - boxes[0].distance >= PhysicsParams::default().size - 1e-3);
+    }
+
+    #[test]
+    fn stops_at_track_ends() {
+        let mut boxes = [physics_box(95.0, 0.0), physics_box(3.0, 0.0)];
+        boxes[0].motion.velocity = 30.0;
+        boxes[1].motion.velocity = -30.0;
+        simulate(&mut boxes, None, 64);
+        assert_eq!(boxes[0].distance, 100.0);
+        assert_eq!(boxes[1].distance, 0.0);
+        assert_eq!(boxes[0].motion.velocity, 0.0);
+    }
+
+    #[test]
+    fn stops_at_paperman() {
+        let params = PhysicsParams::default();
+        let paperman = TrackPoint {
+            track: 0,
+            distance: 20.0,
+        };
+        let mut boxes = [physics_box(10.0, 0.0), physics_box(30.0, 5.0)];
+        boxes[0].motion.velocity = 20.0;
+        simulate(&mut boxes, Some(paperman), 64);
+        assert_eq!(
+            boxes[0].distance,
+            20.0 - params.paperman_radius - params.size / 2.0
+        );
+        assert_eq!(boxes[0].motion.velocity, 0.0);
+        // boxes on other tracks and high flying boxes pass
+        let mut flying = [physics_box(10.0, 6.0)];
+        flying[0].motion.velocity = 20.0;
+        flying[0].motion.vertical_velocity = 10.0;
+        simulate(&mut flying, Some(paperman), 64);
+        assert!(flying[0].distance > 20.0);
+    }
+
+    #[test]
+    fn thrown_boxes_leave_paperman() {
+        let paperman = TrackPoint {
+            track: 0,
+            distance: 50.0,
+        };
+        for velocity in [-10.0, 10.0] {
+            // released at paperman's distance below his height
+            let mut boxes = [physics_box(50.0, 2.0)];
+            boxes[0].motion.velocity = velocity;
+            boxes[0].motion.vertical_velocity = 6.0;
+            simulate(&mut boxes, Some(paperman), 128);
+            let thrown = boxes[0].distance - 50.0;
+            assert!(
+                thrown * velocity.signum() > 5.0,
+                "thrown with {} only to {}",
+                velocity,
+                boxes[0].distance
+            );
+            assert_eq!(boxes[0].motion.height, 0.0);
+        }
+    }
+}
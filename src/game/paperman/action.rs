@@ -0,0 +1,250 @@
+use bevy::prelude::*;
+
+use crate::common::graph::TrackPoint;
+use crate::game::building::BuildingLayout;
+use crate::game::paperbox::{Paperbox, PaperboxCarried, PaperboxMotion};
+
+use super::carry::{reach, release_paperbox, PapermanCarry};
+use super::controller::PapermanControllerState;
+use super::{Paperman, PapermanDirection, PapermanPosition};
+
+#[derive(Resource)]
+pub struct ActionOptions {
+    kick: KeyCode,
+    attack: KeyCode,
+    /// How close paperboxes need to be to get kicked or struck
+    radius: f32,
+    /// Speed along the track of kicked paperboxes
+    kick_speed: f32,
+    /// Speed along the track and upwards of struck paperboxes
+    attack_speed: f32,
+    attack_lift: f32,
+    /// Speed along the track and upwards of thrown paperboxes
+    throw_speed: f32,
+    throw_lift: f32,
+    /// Height above the track thrown paperboxes start from
+    throw_height: f32,
+}
+
+impl Default for ActionOptions {
+    fn default() -> Self {
+        Self {
+            kick: KeyCode::X,
+            attack: KeyCode::C,
+            radius: 2.5,
+            kick_speed: 14.0,
+            attack_speed: 6.0,
+            attack_lift: 9.0,
+            throw_speed: 10.0,
+            throw_lift: 6.0,
+            throw_height: 2.0,
+        }
+    }
+}
+
+/// Kicks or strikes the paperboxes in front of paperman, or throws the carried paperbox
+pub fn action_input_system(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    options: Res<ActionOptions>,
+    mut query: Query<
+        (
+            &PapermanPosition,
+            &PapermanDirection,
+            &mut PapermanControllerState,
+            &mut PapermanCarry,
+        ),
+        With<Paperman>,
+    >,
+    mut paperboxes: Query<(
+        Entity,
+        &mut Paperbox,
+        &mut PaperboxMotion,
+        Has<PaperboxCarried>,
+    )>,
+    layout: Res<BuildingLayout>,
+) {
+    let kick = input.just_pressed(options.kick);
+    let attack = input.just_pressed(options.attack);
+    if !kick && !attack {
+        return;
+    }
+    let Ok((position, direction, mut state, mut carry)) = query.get_single_mut() else {
+        return;
+    };
+    if !matches!(
+        *state,
+        PapermanControllerState::Idle
+            | PapermanControllerState::Running(_)
+            | PapermanControllerState::Blocked(_)
+    ) {
+        return;
+    }
+    let sign = direction.sign();
+
+    if let Some(carried) = carry.paperbox {
+        // kicking with the hands full is not possible, but throwing is
+        if !attack {
+            return;
+        }
+        let Ok((entity, mut paperbox, mut motion, _)) = paperboxes.get_mut(carried) else {
+            warn!("paperman carried the missing paperbox {:?}", carried);
+            carry.paperbox = None;
+            return;
+        };
+        let point = TrackPoint {
+            track: position.track,
+            distance: position.distance,
+        };
+        if !release_paperbox(&mut commands, entity, &mut paperbox, &layout, point) {
+            return;
+        }
+        *motion = PaperboxMotion {
+            height: options.throw_height,
+            ..Default::default()
+        };
+        motion.push(sign * options.throw_speed, options.throw_lift);
+        carry.paperbox = None;
+        *state = PapermanControllerState::Attacking(direction.clone());
+        info!("paperman threw paperbox {:?}", entity);
+        return;
+    }
+
+    *state = if kick {
+        PapermanControllerState::Kicking(direction.clone())
+    } else {
+        PapermanControllerState::Attacking(direction.clone())
+    };
+    for (entity, paperbox, mut motion, carried) in paperboxes.iter_mut() {
+        if carried || reach(position, sign, options.radius, &paperbox).is_none() {
+            continue;
+        }
+        if kick {
+            motion.push(sign * options.kick_speed, 0.0);
+        } else {
+            motion.push(sign * options.attack_speed, options.attack_lift);
+        }
+        info!("paperman hit paperbox {:?}", entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::paperbox::{paperbox_physics_system, PhysicsParams};
+    use crate::game::testing::{advance, building_app, press, release_all, spawn_paperboxes};
+
+    const FRAME: f32 = 1.0 / 60.0;
+
+    fn action_app() -> App {
+        let mut app = building_app(1, 100.0);
+        app.init_resource::<ActionOptions>();
+        app.init_resource::<PhysicsParams>();
+        app.add_systems(
+            Update,
+            (action_input_system, apply_deferred, paperbox_physics_system).chain(),
+        );
+        app
+    }
+
+    fn spawn_paperman(app: &mut App, distance: f32, direction: PapermanDirection) -> Entity {
+        app.world
+            .spawn((
+                Paperman,
+                PapermanPosition { track: 0, distance },
+                direction,
+                PapermanControllerState::Idle,
+                PapermanCarry::default(),
+            ))
+            .id()
+    }
+
+    fn run(app: &mut App, seconds: f32) {
+        release_all(app);
+        for _ in 0..(seconds / FRAME).round() as usize {
+            advance(app, FRAME);
+        }
+    }
+
+    #[test]
+    fn kick_moves_the_paperbox() {
+        let mut app = action_app();
+        spawn_paperman(&mut app, 10.0, PapermanDirection::Right);
+        let paperbox = spawn_paperboxes(
+            &mut app,
+            &[TrackPoint {
+                track: 0,
+                distance: 12.0,
+            }],
+        )[0];
+
+        press(&mut app, KeyCode::X);
+        advance(&mut app, FRAME);
+        assert!(app.world.get::<PaperboxMotion>(paperbox).unwrap().velocity > 0.0);
+        run(&mut app, 2.0);
+
+        let distance = app.world.get::<Paperbox>(paperbox).unwrap().distance;
+        assert!(
+            distance > 15.0,
+            "kicked paperbox only moved to {}",
+            distance
+        );
+        assert!(app
+            .world
+            .get::<PaperboxMotion>(paperbox)
+            .unwrap()
+            .is_resting());
+    }
+
+    #[test]
+    fn throw_lands_in_front_of_paperman() {
+        let mut app = action_app();
+        let paperman = spawn_paperman(&mut app, 50.0, PapermanDirection::Left);
+        let paperbox = spawn_paperboxes(
+            &mut app,
+            &[TrackPoint {
+                track: 0,
+                distance: 50.0,
+            }],
+        )[0];
+        app.world.entity_mut(paperbox).insert(PaperboxCarried);
+        app.world
+            .get_mut::<PapermanCarry>(paperman)
+            .unwrap()
+            .paperbox = Some(paperbox);
+
+        press(&mut app, KeyCode::C);
+        advance(&mut app, FRAME);
+        assert_eq!(
+            app.world.get::<PapermanCarry>(paperman).unwrap().paperbox,
+            None
+        );
+        assert!(app.world.get::<PaperboxCarried>(paperbox).is_none());
+        run(&mut app, 2.0);
+
+        let distance = app.world.get::<Paperbox>(paperbox).unwrap().distance;
+        assert!(distance < 45.0, "thrown paperbox landed at {}", distance);
+        let motion = app.world.get::<PaperboxMotion>(paperbox).unwrap();
+        assert_eq!(motion.height, 0.0);
+        assert!(motion.is_resting());
+    }
+
+    #[test]
+    fn throwing_a_missing_paperbox_empties_the_hands() {
+        let mut app = action_app();
+        let paperman = spawn_paperman(&mut app, 50.0, PapermanDirection::Left);
+        let missing = app.world.spawn_empty().id();
+        app.world.despawn(missing);
+        app.world
+            .get_mut::<PapermanCarry>(paperman)
+            .unwrap()
+            .paperbox = Some(missing);
+
+        press(&mut app, KeyCode::C);
+        advance(&mut app, FRAME);
+        assert_eq!(
+            app.world.get::<PapermanCarry>(paperman).unwrap().paperbox,
+            None
+        );
+    }
+}
@@ -11,6 +11,11 @@ pub enum PapermanAnimationState {
     Turning,
     Walking,
     Running,
+    /// Walking with a paperbox in the hands
+    Carrying,
+    Kicking,
+    /// Striking or throwing, the glTF calls the clip `strike`
+    Attacking,
     #[default]
     Idle,
 }
@@ -82,6 +87,31 @@ pub fn setup_animation_system(mut commands: Commands, paperman: Res<PapermanReso
                 ..Default::default()
             },
         ),
+        (
+            PapermanAnimationState::Carrying,
+            PapermanAnimationClip {
+                handle: paperman.animations.get("walking").unwrap().clone(),
+                looped: true,
+                speed: 0.6,
+                transition: Duration::from_millis(400),
+            },
+        ),
+        (
+            PapermanAnimationState::Kicking,
+            PapermanAnimationClip {
+                handle: paperman.animations.get("kick").unwrap().clone(),
+                transition: Duration::from_millis(200),
+                ..Default::default()
+            },
+        ),
+        (
+            PapermanAnimationState::Attacking,
+            PapermanAnimationClip {
+                handle: paperman.animations.get("strike").unwrap().clone(),
+                transition: Duration::from_millis(200),
+                ..Default::default()
+            },
+        ),
         (
             PapermanAnimationState::Turning,
             PapermanAnimationClip {
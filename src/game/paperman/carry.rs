@@ -0,0 +1,223 @@
+use bevy::prelude::*;
+
+use crate::common::graph::TrackPoint;
+use crate::game::building::BuildingLayout;
+use crate::game::paperbox::{Paperbox, PaperboxCarried, PAPERBOX_SIZE};
+
+use super::controller::PapermanControllerState;
+use super::{Paperman, PapermanDirection, PapermanPosition};
+
+#[derive(Resource)]
+pub struct CarryOptions {
+    /// Picks up or drops a paperbox
+    action: KeyCode,
+    /// How close paperman needs to be to a paperbox to pick it up
+    pickup_radius: f32,
+    /// How far in front of paperman a paperbox is dropped
+    drop_distance: f32,
+    /// Running speed while carrying, relative to the normal speed
+    pub speed_factor: f32,
+}
+
+impl Default for CarryOptions {
+    fn default() -> Self {
+        Self {
+            action: KeyCode::Space,
+            pickup_radius: 2.0,
+            drop_distance: 1.5,
+            speed_factor: 0.6,
+        }
+    }
+}
+
+/// The paperbox paperman is carrying, if any.
+///
+/// Carrying is kept next to `PapermanControllerState` instead of being one of its states:
+/// paperman keeps running, turning and taking the stairs with a box in his hands, so a
+/// `Carrying` state would have to duplicate each of them. The controller reads this component
+/// for the carrying speed and animations.
+#[derive(Component, Debug, Default)]
+pub struct PapermanCarry {
+    pub paperbox: Option<Entity>,
+}
+
+/// The bone of the paperman scene paperboxes are attached to
+const HAND_BONE: &str = "hand.R";
+
+/// Returns true if the entity is a descendant of the ancestor.
+fn is_descendant_of(entity: Entity, ancestor: Entity, parents: &Query<&Parent>) -> bool {
+    parents
+        .iter_ancestors(entity)
+        .any(|parent| parent == ancestor)
+}
+
+/// Returns how far the paperbox is in front of paperman if it is on his track within
+/// `radius`, boxes right behind him are still in reach.
+pub(super) fn reach(
+    position: &PapermanPosition,
+    sign: f32,
+    radius: f32,
+    paperbox: &Paperbox,
+) -> Option<f32> {
+    let ahead = (paperbox.distance - position.distance) * sign;
+    (paperbox.track == position.track && ahead > -radius / 4.0 && ahead.abs() <= radius)
+        .then_some(ahead)
+}
+
+/// Returns the paperbox closest to paperman in reach, see [`reach`].
+fn paperbox_in_reach<'a>(
+    position: &PapermanPosition,
+    sign: f32,
+    radius: f32,
+    paperboxes: impl Iterator<Item = (Entity, &'a Paperbox)>,
+) -> Option<Entity> {
+    paperboxes
+        .filter_map(|(entity, paperbox)| Some((entity, reach(position, sign, radius, paperbox)?)))
+        .min_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+        .map(|(entity, _)| entity)
+}
+
+/// Detaches the carried paperbox from paperman's hand and puts it on the track, returns false
+/// if the track doesn't exist and the paperbox stays in his hand.
+pub(super) fn release_paperbox(
+    commands: &mut Commands,
+    entity: Entity,
+    paperbox: &mut Paperbox,
+    layout: &BuildingLayout,
+    point: TrackPoint,
+) -> bool {
+    let Some(track) = layout.tracks.tracks().get(point.track) else {
+        return false;
+    };
+    let distance = track.clamp(point.distance);
+    *paperbox = Paperbox {
+        track: point.track,
+        distance,
+    };
+    commands
+        .entity(entity)
+        .remove_parent()
+        .remove::<PaperboxCarried>()
+        .insert(Transform::from_translation(track.position_at(distance)));
+    true
+}
+
+/// Picks up the paperbox in reach or drops the carried one in front of paperman
+#[allow(clippy::too_many_arguments)]
+pub fn carry_input_system(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    options: Res<CarryOptions>,
+    mut query: Query<
+        (
+            Entity,
+            &PapermanPosition,
+            &PapermanDirection,
+            &PapermanControllerState,
+            &mut PapermanCarry,
+        ),
+        With<Paperman>,
+    >,
+    mut paperboxes: Query<(Entity, &mut Paperbox, Has<PaperboxCarried>)>,
+    bones: Query<(Entity, &Name, &GlobalTransform)>,
+    parents: Query<&Parent>,
+    layout: Res<BuildingLayout>,
+) {
+    if !input.just_pressed(options.action) {
+        return;
+    }
+    let Ok((paperman, position, direction, state, mut carry)) = query.get_single_mut() else {
+        return;
+    };
+    // boxes can't be handled while turning, switching floors or kicking
+    if let PapermanControllerState::Turning(_)
+    | PapermanControllerState::SwitchingFloor(_)
+    | PapermanControllerState::Kicking(_)
+    | PapermanControllerState::Attacking(_) = state
+    {
+        return;
+    }
+
+    if let Some(carried) = carry.paperbox {
+        let Ok((entity, mut paperbox, _)) = paperboxes.get_mut(carried) else {
+            warn!("paperman carried the missing paperbox {:?}", carried);
+            carry.paperbox = None;
+            return;
+        };
+        let point = TrackPoint {
+            track: position.track,
+            distance: position.distance + direction.sign() * options.drop_distance,
+        };
+        if !release_paperbox(&mut commands, entity, &mut paperbox, &layout, point) {
+            return;
+        }
+        carry.paperbox = None;
+        info!("paperman dropped paperbox {:?}", entity);
+        return;
+    }
+
+    let in_reach = paperbox_in_reach(
+        position,
+        direction.sign(),
+        options.pickup_radius,
+        paperboxes
+            .iter()
+            .filter(|(_, _, carried)| !carried)
+            .map(|(entity, paperbox, _)| (entity, paperbox)),
+    );
+    let Some(paperbox) = in_reach else {
+        return;
+    };
+    let hand = bones.iter().find(|(entity, name, _)| {
+        name.as_str() == HAND_BONE && is_descendant_of(*entity, paperman, &parents)
+    });
+    let Some((hand, _, hand_transform)) = hand else {
+        warn!("paperman has no {} bone to carry the paperbox", HAND_BONE);
+        return;
+    };
+    // center the box on the hand, keep it upright and unscaled by the bone
+    let carried = GlobalTransform::from(
+        Transform::from_translation(hand_transform.translation() - Vec3::Y * PAPERBOX_SIZE / 2.0)
+            .with_rotation(direction.as_quat()),
+    );
+    commands
+        .entity(paperbox)
+        .insert((PaperboxCarried, carried.reparented_to(hand_transform)))
+        .set_parent(hand);
+    carry.paperbox = Some(paperbox);
+    info!("paperman picked up paperbox {:?}", paperbox);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paperbox(track: usize, distance: f32) -> Paperbox {
+        Paperbox { track, distance }
+    }
+
+    #[test]
+    fn paperbox_in_reach_picks_closest_ahead() {
+        let position = PapermanPosition {
+            track: 0,
+            distance: 10.0,
+        };
+        let boxes = [
+            (Entity::from_raw(0), paperbox(0, 11.5)),
+            (Entity::from_raw(1), paperbox(0, 10.8)),
+            (Entity::from_raw(2), paperbox(1, 10.0)),
+            (Entity::from_raw(3), paperbox(0, 8.5)),
+        ];
+        let iter = || boxes.iter().map(|(entity, paperbox)| (*entity, paperbox));
+        assert_eq!(
+            paperbox_in_reach(&position, 1.0, 2.0, iter()),
+            Some(Entity::from_raw(1))
+        );
+        // the box right behind is out of reach when turned around
+        assert_eq!(
+            paperbox_in_reach(&position, -1.0, 2.0, iter()),
+            Some(Entity::from_raw(3))
+        );
+        assert_eq!(paperbox_in_reach(&position, 1.0, 0.5, iter()), None);
+    }
+}
@@ -1,20 +1,41 @@
 use bevy::ecs::query::WorldQuery;
 use bevy::prelude::*;
 
+use crate::common::graph::{ConnectorKind, TrackConnection, TrackPoint};
+use crate::game::building::BuildingLayout;
+
 use super::{
     animation::{PapermanAnimationFinishedEvent, PapermanAnimationState},
-    PapermanDirection, PapermanPosition, PapermanVelocity,
+    carry::{CarryOptions, PapermanCarry},
+    PapermanDirection, PapermanFloorTransition, PapermanPosition, PapermanVelocity,
 };
 
 pub struct KeyMap {
     left: KeyCode,
     right: KeyCode,
+    up: KeyCode,
+    down: KeyCode,
 }
 
 #[derive(Resource)]
 pub struct Options {
     keymap: KeyMap,
     acceleration: f32,
+    /// How close paperman needs to be to a connector to switch floors
+    connector_radius: f32,
+    /// Seconds it takes to switch floors using stairs
+    stairs_duration: f32,
+    /// Seconds it takes to switch floors using an elevator
+    elevator_duration: f32,
+}
+
+impl Options {
+    fn floor_transition_duration(&self, kind: ConnectorKind) -> f32 {
+        match kind {
+            ConnectorKind::Stairs => self.stairs_duration,
+            ConnectorKind::Elevator => self.elevator_duration,
+        }
+    }
 }
 
 impl Default for Options {
@@ -23,8 +44,13 @@ impl Default for Options {
             keymap: KeyMap {
                 left: KeyCode::Left,
                 right: KeyCode::Right,
+                up: KeyCode::Up,
+                down: KeyCode::Down,
             },
             acceleration: 10.0,
+            connector_radius: 1.5,
+            stairs_duration: 1.2,
+            elevator_duration: 2.0,
         }
     }
 }
@@ -35,6 +61,14 @@ pub enum PapermanControllerState {
     Idle,
     Turning(PapermanDirection),
     Running(PapermanDirection),
+    /// Trying to run past the end of the track
+    Blocked(PapermanDirection),
+    /// Moving to another floor using stairs or an elevator
+    SwitchingFloor(TrackConnection),
+    /// Kicking the paperboxes in front
+    Kicking(PapermanDirection),
+    /// Striking the paperboxes in front or throwing the carried paperbox
+    Attacking(PapermanDirection),
 }
 
 #[derive(WorldQuery)]
@@ -46,6 +80,8 @@ pub struct PapermanControllerQuery {
     velocity: &'static mut PapermanVelocity,
     state: &'static mut PapermanControllerState,
     animation_state: &'static mut PapermanAnimationState,
+    floor_transition: &'static mut PapermanFloorTransition,
+    carry: &'static PapermanCarry,
 }
 
 /// Returns the direction of movement for the given input keys.
@@ -63,17 +99,60 @@ pub fn movement_direction(
     }
 }
 
+/// Returns the connection to the floor above or below if paperman stands next to a connector
+/// and the up or down key was pressed.
+fn floor_connection(
+    input: &Res<Input<KeyCode>>,
+    options: &Options,
+    layout: &BuildingLayout,
+    position: &PapermanPosition,
+) -> Option<TrackConnection> {
+    let up = input.just_pressed(options.keymap.up);
+    let down = input.just_pressed(options.keymap.down);
+    if !up && !down {
+        return None;
+    }
+    let point = TrackPoint {
+        track: position.track,
+        distance: position.distance,
+    };
+    // tracks are ordered by floor
+    layout
+        .tracks
+        .connection_near(point, options.connector_radius, |connection| {
+            (up && connection.to.track > connection.from.track)
+                || (down && connection.to.track < connection.from.track)
+        })
+}
+
 /// Update controller state from user input
 pub fn update_input_state_system(
     mut query: Query<PapermanControllerQuery>,
     input: Res<Input<KeyCode>>,
     options: Res<Options>,
+    layout: Res<BuildingLayout>,
 ) {
     if let Ok(mut paperman) = query.get_single_mut() {
+        // switching floors, kicking and attacking can't be interrupted
+        if let PapermanControllerState::SwitchingFloor(_)
+        | PapermanControllerState::Kicking(_)
+        | PapermanControllerState::Attacking(_) = *paperman.state
+        {
+            return;
+        }
+
+        let track = &layout.tracks[paperman.position.track];
         let direction = movement_direction(&input, options.keymap.left, options.keymap.right);
-        let next_state = if let Some(direction) = direction {
+        let connection = floor_connection(&input, &options, &layout, &paperman.position);
+        let next_state = if let Some(connection) = connection {
+            PapermanControllerState::SwitchingFloor(connection)
+        } else if let Some(direction) = direction {
+            // if the character is facing the end of the track it can't move any further
+            if *paperman.direction == direction && paperman.position.at_end(track, &direction) {
+                PapermanControllerState::Blocked(direction)
+            }
             // if the character is already facing in this direction switch to the running state
-            if *paperman.direction == direction {
+            else if *paperman.direction == direction {
                 PapermanControllerState::Running(direction)
             }
             // if not switch to the turning state
@@ -87,13 +166,21 @@ pub fn update_input_state_system(
         };
 
         if next_state != *paperman.state {
-            // can't go from turning to idle
+            // can't go from turning to idle or to another floor
             if let PapermanControllerState::Turning(_) = *paperman.state {
-                if next_state == PapermanControllerState::Idle {
+                if let PapermanControllerState::Idle | PapermanControllerState::SwitchingFloor(_) =
+                    next_state
+                {
                     return;
                 }
             }
 
+            if let PapermanControllerState::SwitchingFloor(_) = next_state {
+                paperman
+                    .floor_transition
+                    .start(&layout.tracks, &paperman.position);
+            }
+
             *paperman.state = next_state;
             info!("paperman.state = {:?}", paperman.state);
         }
@@ -101,19 +188,34 @@ pub fn update_input_state_system(
 }
 
 /// Update animation state from controller state
+#[allow(clippy::type_complexity)]
 pub fn update_animation_state_system(
-    mut query: Query<PapermanControllerQuery, Changed<PapermanControllerState>>,
+    mut query: Query<
+        PapermanControllerQuery,
+        Or<(Changed<PapermanControllerState>, Changed<PapermanCarry>)>,
+    >,
 ) {
     if let Ok(mut paperman) = query.get_single_mut() {
         info!("paperman.state changed = {:?}", paperman.state);
 
+        let carrying = paperman.carry.paperbox.is_some();
         let next_animation_state = match *paperman.state {
+            PapermanControllerState::Running(_) if carrying => PapermanAnimationState::Carrying,
             PapermanControllerState::Running(_) => {
                 // TODO use velocity to decide between walking and running
                 PapermanAnimationState::Walking
             }
             PapermanControllerState::Turning(_) => PapermanAnimationState::Turning,
-            PapermanControllerState::Idle => PapermanAnimationState::Idle,
+            PapermanControllerState::Idle | PapermanControllerState::Blocked(_) => {
+                PapermanAnimationState::Idle
+            }
+            PapermanControllerState::Kicking(_) => PapermanAnimationState::Kicking,
+            PapermanControllerState::Attacking(_) => PapermanAnimationState::Attacking,
+            PapermanControllerState::SwitchingFloor(connection) => match connection.kind {
+                ConnectorKind::Stairs if carrying => PapermanAnimationState::Carrying,
+                ConnectorKind::Stairs => PapermanAnimationState::Walking,
+                ConnectorKind::Elevator => PapermanAnimationState::Idle,
+            },
         };
 
         if next_animation_state != *paperman.animation_state {
@@ -144,20 +246,80 @@ pub fn finished_turning_animation_system(
     }
 }
 
+/// Returns to idle when the kick or attack animation finished
+pub fn finished_action_animation_system(
+    mut query: Query<PapermanControllerQuery>,
+    mut event_reader: EventReader<PapermanAnimationFinishedEvent>,
+) {
+    if let Ok(mut paperman) = query.get_single_mut() {
+        for event in event_reader.read() {
+            let finished = matches!(
+                (&event.state, paperman.state.as_ref()),
+                (
+                    PapermanAnimationState::Kicking,
+                    PapermanControllerState::Kicking(_)
+                ) | (
+                    PapermanAnimationState::Attacking,
+                    PapermanControllerState::Attacking(_)
+                )
+            );
+            if finished {
+                *paperman.state = PapermanControllerState::Idle;
+            }
+        }
+    }
+}
+
+/// Advances the transition to another floor, places the character on the new track when
+/// the transition is finished.
+pub fn floor_transition_system(
+    mut query: Query<PapermanControllerQuery>,
+    time: Res<Time>,
+    options: Res<Options>,
+) {
+    let mut result = query.single_mut();
+    if let PapermanControllerState::SwitchingFloor(connection) = *result.state {
+        let duration = options.floor_transition_duration(connection.kind);
+        result.floor_transition.progress += time.delta_seconds() / duration;
+
+        if result.floor_transition.progress >= 1.0 {
+            info!("paperman switched to track {}", connection.to.track);
+            *result.position = PapermanPosition {
+                track: connection.to.track,
+                distance: connection.to.distance,
+            };
+            *result.floor_transition = PapermanFloorTransition::default();
+            *result.state = PapermanControllerState::Idle;
+        }
+    }
+}
+
+/// Moves the character along its track, stops and blocks at the ends of the track
 pub fn movement_system(
     mut query: Query<PapermanControllerQuery>,
     time: Res<Time>,
     options: Res<Options>,
+    carry_options: Res<CarryOptions>,
+    layout: Res<BuildingLayout>,
 ) {
     let mut result = query.single_mut();
     let dt = time.delta_seconds();
 
-    let velocity = if let PapermanControllerState::Running(direction) = result.state.as_ref() {
-        direction.forward() * options.acceleration * dt
-    } else {
-        Vec3::ZERO
-    };
+    if let PapermanControllerState::Running(direction) = result.state.as_ref() {
+        let direction = direction.clone();
+        let track = &layout.tracks[result.position.track];
+        // carrying a paperbox slows paperman down
+        let speed = if result.carry.paperbox.is_some() {
+            options.acceleration * carry_options.speed_factor
+        } else {
+            options.acceleration
+        };
+        let distance = result.position.distance + direction.sign() * speed * dt;
 
-    let position = result.position.0 + velocity;
-    result.position.0 = position;
+        result.position.distance = track.clamp(distance);
+        if result.position.at_end(track, &direction) {
+            info!("paperman reached the end of the track");
+            *result.state = PapermanControllerState::Blocked(direction);
+        }
+    }
 }
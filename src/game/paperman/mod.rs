@@ -1,21 +1,33 @@
 use bevy::ecs::query::WorldQuery;
 use bevy::prelude::*;
 
+use self::action::ActionOptions;
 use self::animation::{
     setup_animation_system, PapermanAnimationFinishedEvent, PapermanAnimationState,
 };
+use self::carry::{CarryOptions, PapermanCarry};
 use self::controller::{Options, PapermanControllerState};
 
+use crate::common::graph::{TrackGraph, TrackPoint};
+use crate::common::track::Track;
+
 use super::{
-    assets::{BuildingResource, PapermanResource},
+    assets::PapermanResource,
+    building::{
+        prepare_scenes_system, respawn_scenes_system, stream_endless_scenes_system, BuildingLayout,
+        BuildingRespawnedEvent, BuildingTrimmedEvent, LevelRestartEvent,
+    },
     states::GameState,
 };
 
+mod action;
 mod animation;
+mod carry;
 mod controller;
 
 #[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
 enum PapermanSystemSet {
+    Reload,
     Controller,
     Animation,
     Update,
@@ -26,11 +38,16 @@ pub struct PapermanPlugin;
 impl Plugin for PapermanPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Options::default());
+        app.init_resource::<CarryOptions>();
+        app.init_resource::<ActionOptions>();
         app.insert_resource(CameraZoom::default());
         app.add_event::<PapermanAnimationFinishedEvent>();
         app.add_systems(
             OnEnter(GameState::GameLoading),
-            (prepare_paperman_system, setup_animation_system),
+            (
+                prepare_paperman_system.after(prepare_scenes_system),
+                setup_animation_system,
+            ),
         );
         app.add_systems(
             OnExit(GameState::GameLoading),
@@ -39,6 +56,7 @@ impl Plugin for PapermanPlugin {
         app.configure_sets(
             Update,
             (
+                PapermanSystemSet::Reload,
                 PapermanSystemSet::Controller,
                 PapermanSystemSet::Update,
                 PapermanSystemSet::Animation,
@@ -50,10 +68,22 @@ impl Plugin for PapermanPlugin {
         app.add_systems(
             Update,
             (
+                (
+                    reproject_paperman_system,
+                    shift_paperman_system,
+                    restart_paperman_system,
+                )
+                    .after(respawn_scenes_system)
+                    .after(stream_endless_scenes_system)
+                    .in_set(PapermanSystemSet::Reload),
                 (
                     controller::update_input_state_system,
+                    carry::carry_input_system,
+                    action::action_input_system,
                     controller::update_animation_state_system,
                     controller::finished_turning_animation_system,
+                    controller::finished_action_animation_system,
+                    controller::floor_transition_system,
                     controller::movement_system,
                 )
                     .in_set(PapermanSystemSet::Controller),
@@ -76,9 +106,44 @@ pub struct CameraZoom(f32);
 #[derive(Component, Debug)]
 pub struct Paperman;
 
-/// Paperman position
+/// Paperman position, as distance along one of the building tracks
 #[derive(Component, Debug)]
-pub struct PapermanPosition(Vec3);
+pub struct PapermanPosition {
+    pub track: usize,
+    pub distance: f32,
+}
+
+impl PapermanPosition {
+    /// Returns true if the position is at the end of the track in the given direction.
+    pub fn at_end(&self, track: &Track, direction: &PapermanDirection) -> bool {
+        match direction {
+            PapermanDirection::Left => self.distance <= 0.0,
+            PapermanDirection::Right => self.distance >= track.length(),
+        }
+    }
+}
+
+/// Transition between two floors, blends from the start position to the connector end on the
+/// other floor.
+#[derive(Component, Debug, Default)]
+pub struct PapermanFloorTransition {
+    pub start: Vec3,
+    pub progress: f32,
+}
+
+impl PapermanFloorTransition {
+    pub fn start(&mut self, tracks: &TrackGraph, position: &PapermanPosition) {
+        self.start = tracks[position.track].position_at(position.distance);
+        self.progress = 0.0;
+    }
+
+    /// Returns the position during the transition, eased in and out
+    pub fn position(&self, tracks: &TrackGraph, to: TrackPoint) -> Vec3 {
+        let t = self.progress.clamp(0.0, 1.0);
+        let t = t * t * (3.0 - 2.0 * t);
+        self.start.lerp(tracks.position_at(to), t)
+    }
+}
 
 /// Paperman direction, left or right
 #[derive(Component, Debug, PartialEq, Clone, Default)]
@@ -99,10 +164,11 @@ impl PapermanDirection {
         }
     }
 
-    pub fn forward(&self) -> Vec3 {
+    /// Sign of the movement along the track, tracks are ordered from left to right
+    pub fn sign(&self) -> f32 {
         match self {
-            Self::Left => -Vec3::X,
-            Self::Right => Vec3::X,
+            Self::Left => -1.0,
+            Self::Right => 1.0,
         }
     }
 }
@@ -117,26 +183,64 @@ struct PapermanTransformQuery {
     entity: Entity,
     position: &'static PapermanPosition,
     rotation: &'static PapermanDirection,
+    state: &'static PapermanControllerState,
+    floor_transition: &'static PapermanFloorTransition,
     transform: &'static mut Transform,
 }
 
-fn transform_from_player(position: &PapermanPosition, rotation: &PapermanDirection) -> Transform {
-    Transform::from_translation(position.0)
+fn transform_from_player(
+    layout: &BuildingLayout,
+    player: &PapermanTransformQueryItem,
+) -> Transform {
+    let translation = if let PapermanControllerState::SwitchingFloor(connection) = player.state {
+        player
+            .floor_transition
+            .position(&layout.tracks, connection.to)
+    } else {
+        layout.tracks[player.position.track].position_at(player.position.distance)
+    };
+    Transform::from_translation(translation)
         .with_scale(Vec3::splat(2.0))
-        .with_rotation(rotation.as_quat())
+        .with_rotation(player.rotation.as_quat())
+}
+
+/// Distance along the first track paperman is placed at without a spawn_player marker
+const SPAWN_DISTANCE: f32 = 3.0;
+
+/// Returns where paperman starts, at the spawn_player marker if the building has one
+fn start_point(layout: &BuildingLayout) -> Option<TrackPoint> {
+    let first = layout.tracks.tracks().first()?;
+    Some(
+        layout
+            .player_spawn
+            .and_then(|spawn| layout.tracks.closest_point(spawn))
+            .unwrap_or(TrackPoint {
+                track: 0,
+                distance: first.clamp(SPAWN_DISTANCE),
+            }),
+    )
 }
 
 fn prepare_paperman_system(
     mut commands: Commands,
-    building: Res<BuildingResource>,
+    layout: Res<BuildingLayout>,
     paperman: Res<PapermanResource>,
 ) {
+    let Some(start) = start_point(&layout) else {
+        error!("The building has no tracks to place paperman on");
+        return;
+    };
     commands.spawn((
         Paperman,
-        PapermanPosition(building.tracks.first().unwrap().first() + (Vec3::X * 3.0)),
+        PapermanPosition {
+            track: start.track,
+            distance: start.distance,
+        },
         PapermanDirection::Right,
         PapermanVelocity(Vec3::ZERO),
         PapermanControllerState::default(),
+        PapermanFloorTransition::default(),
+        PapermanCarry::default(),
         PapermanAnimationState::default(),
         SceneBundle {
             scene: paperman.scene.clone(),
@@ -145,12 +249,121 @@ fn prepare_paperman_system(
     ));
 }
 
+/// Moves paperman onto the closest point of the respawned track, keeps the current floor.
+/// The carried paperbox is respawned with the others and leaves his hand.
+fn reproject_paperman_system(
+    mut events: EventReader<BuildingRespawnedEvent>,
+    mut query: Query<(
+        &mut PapermanPosition,
+        &mut PapermanControllerState,
+        &mut PapermanFloorTransition,
+        &mut PapermanCarry,
+        &Transform,
+    )>,
+    layout: Res<BuildingLayout>,
+) {
+    if events.read().last().is_none() {
+        return;
+    }
+    for (.., mut carry, _) in query.iter_mut() {
+        carry.paperbox = None;
+    }
+    if layout.tracks.is_empty() {
+        return;
+    }
+    for (mut position, mut state, mut floor_transition, _, transform) in query.iter_mut() {
+        let track = position.track.min(layout.tracks.len() - 1);
+        let distance = layout.tracks[track].distance_of(transform.translation);
+        info!("reprojected paperman to track {} at {}", track, distance);
+        *position = PapermanPosition { track, distance };
+        // the connector might not exist anymore
+        if let PapermanControllerState::SwitchingFloor(_) = *state {
+            *state = PapermanControllerState::Idle;
+            *floor_transition = PapermanFloorTransition::default();
+        }
+    }
+}
+
+/// Moves paperman back to his start when the level restarts. The carried paperbox is respawned
+/// with the others and leaves his hand.
+fn restart_paperman_system(
+    mut events: EventReader<LevelRestartEvent>,
+    mut query: Query<(
+        &mut PapermanPosition,
+        &mut PapermanDirection,
+        &mut PapermanControllerState,
+        &mut PapermanFloorTransition,
+        &mut PapermanCarry,
+    )>,
+    layout: Res<BuildingLayout>,
+) {
+    if events.read().last().is_none() {
+        return;
+    }
+    let Some(start) = start_point(&layout) else {
+        return;
+    };
+    for (mut position, mut direction, mut state, mut floor_transition, mut carry) in
+        query.iter_mut()
+    {
+        info!(
+            "restarted paperman on track {} at {}",
+            start.track, start.distance
+        );
+        *position = PapermanPosition {
+            track: start.track,
+            distance: start.distance,
+        };
+        *direction = PapermanDirection::default();
+        *state = PapermanControllerState::default();
+        *floor_transition = PapermanFloorTransition::default();
+        carry.paperbox = None;
+    }
+}
+
+/// Moves paperman back with the tracks trimmed behind him, his position stays the same
+fn shift_paperman_system(
+    mut events: EventReader<BuildingTrimmedEvent>,
+    mut query: Query<(&mut PapermanPosition, &mut PapermanControllerState)>,
+) {
+    for event in events.read() {
+        for (mut position, mut state) in query.iter_mut() {
+            let track = position.track;
+            position.distance = shifted_track_distance(event, track, position.distance);
+            if let PapermanControllerState::SwitchingFloor(connection) = state.as_mut() {
+                for end in [&mut connection.from, &mut connection.to] {
+                    end.distance = shifted_track_distance(event, end.track, end.distance);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the distance on the trimmed track. The endless building only trims far behind
+/// paperman, a distance on the removed part is a bug and is clamped to the track start.
+fn shifted_track_distance(event: &BuildingTrimmedEvent, track: usize, distance: f32) -> f32 {
+    let shifted = event.shifted(track, distance);
+    debug_assert!(
+        shifted.is_some(),
+        "trimmed track {} in front of paperman at {}",
+        track,
+        distance
+    );
+    shifted.unwrap_or_else(|| {
+        error!(
+            "Trimmed track {} in front of paperman at {}",
+            track, distance
+        );
+        0.0
+    })
+}
+
 fn zoom_camera(keyboard_input: Res<Input<KeyCode>>, mut zoom: ResMut<CameraZoom>, time: Res<Time>) {
     let dt = time.delta_seconds();
     const ZOOM_SPEED: f32 = 23.0;
-    if keyboard_input.pressed(KeyCode::Up) {
+    if keyboard_input.pressed(KeyCode::PageUp) {
         zoom.0 += ZOOM_SPEED * dt;
-    } else if keyboard_input.pressed(KeyCode::Down) {
+    } else if keyboard_input.pressed(KeyCode::PageDown) {
         zoom.0 -= ZOOM_SPEED * dt;
     }
 }
@@ -160,9 +373,10 @@ fn update_paperman_transform_system(
     mut query: Query<PapermanTransformQuery>,
     camera: Query<Entity, With<Camera3d>>,
     zoom: Res<CameraZoom>,
+    layout: Res<BuildingLayout>,
 ) {
     if let Ok(mut result) = query.get_single_mut() {
-        let transform = transform_from_player(result.position, result.rotation);
+        let transform = transform_from_player(&layout, &result);
 
         *result.transform = transform;
 
@@ -176,3 +390,127 @@ fn update_paperman_transform_system(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::graph::{ConnectorKind, TrackConnection};
+    use crate::game::testing::{building_app, FLOOR_HEIGHT};
+
+    #[test]
+    fn respawn_empties_the_hands() {
+        let mut app = building_app(2, 100.0);
+        app.add_event::<BuildingRespawnedEvent>();
+        app.add_systems(Update, reproject_paperman_system);
+        let paperman = app
+            .world
+            .spawn((
+                PapermanPosition {
+                    track: 1,
+                    distance: 10.0,
+                },
+                PapermanControllerState::Idle,
+                PapermanFloorTransition::default(),
+                PapermanCarry {
+                    paperbox: Some(Entity::from_raw(42)),
+                },
+                Transform::from_xyz(20.0, 4.0, 0.0),
+            ))
+            .id();
+
+        app.world.send_event(BuildingRespawnedEvent);
+        app.update();
+
+        assert_eq!(
+            app.world.get::<PapermanCarry>(paperman).unwrap().paperbox,
+            None
+        );
+        let position = app.world.get::<PapermanPosition>(paperman).unwrap();
+        assert_eq!((position.track, position.distance), (1, 20.0));
+    }
+
+    #[test]
+    fn restart_moves_paperman_to_his_start() {
+        let mut app = building_app(2, 100.0);
+        app.add_event::<LevelRestartEvent>();
+        app.add_systems(Update, restart_paperman_system);
+        let paperman = app
+            .world
+            .spawn((
+                PapermanPosition {
+                    track: 1,
+                    distance: 60.0,
+                },
+                PapermanDirection::Left,
+                PapermanControllerState::Idle,
+                PapermanFloorTransition::default(),
+                PapermanCarry {
+                    paperbox: Some(Entity::from_raw(42)),
+                },
+            ))
+            .id();
+
+        app.world.send_event(LevelRestartEvent);
+        app.update();
+        let position = app.world.get::<PapermanPosition>(paperman).unwrap();
+        assert_eq!((position.track, position.distance), (0, SPAWN_DISTANCE));
+        assert_eq!(
+            app.world.get::<PapermanDirection>(paperman),
+            Some(&PapermanDirection::Right)
+        );
+        assert_eq!(
+            app.world.get::<PapermanCarry>(paperman).unwrap().paperbox,
+            None
+        );
+
+        app.world.resource_mut::<BuildingLayout>().player_spawn =
+            Some(Vec3::new(40.0, FLOOR_HEIGHT, 0.0));
+        app.world.send_event(LevelRestartEvent);
+        app.update();
+        let position = app.world.get::<PapermanPosition>(paperman).unwrap();
+        assert_eq!((position.track, position.distance), (1, 40.0));
+    }
+
+    #[test]
+    fn trimming_shifts_paperman_back() {
+        let mut app = building_app(2, 100.0);
+        app.add_event::<BuildingTrimmedEvent>();
+        app.add_systems(Update, shift_paperman_system);
+        let connection = TrackConnection {
+            kind: ConnectorKind::Stairs,
+            from: TrackPoint {
+                track: 0,
+                distance: 50.0,
+            },
+            to: TrackPoint {
+                track: 1,
+                distance: 55.0,
+            },
+        };
+        let paperman = app
+            .world
+            .spawn((
+                PapermanPosition {
+                    track: 0,
+                    distance: 50.0,
+                },
+                PapermanControllerState::SwitchingFloor(connection),
+            ))
+            .id();
+
+        app.world.send_event(BuildingTrimmedEvent {
+            removed: vec![30.0, 40.0],
+        });
+        app.update();
+
+        let position = app.world.get::<PapermanPosition>(paperman).unwrap();
+        assert_eq!((position.track, position.distance), (0, 20.0));
+        let PapermanControllerState::SwitchingFloor(connection) =
+            app.world.get::<PapermanControllerState>(paperman).unwrap()
+        else {
+            panic!("paperman stopped switching floors");
+        };
+        assert_eq!(connection.from.distance, 20.0);
+        assert_eq!(connection.to.distance, 15.0);
+    }
+}
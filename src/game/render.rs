@@ -1,15 +1,38 @@
 use bevy::prelude::*;
+use bevy::render::view::NoFrustumCulling;
+
+use super::building::BuildingScene;
 
 pub struct RenderPlugin;
 
 const CLEAR_COLOR: &str = "#000000";
 
+/// Toggles frustum culling to compare the rendering performance
+const TOGGLE_CULLING_KEY: KeyCode = KeyCode::F3;
+
 impl Plugin for RenderPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(ClearColor(
             Color::hex(CLEAR_COLOR).expect("Invalid ClearColor!"),
         ));
+        app.init_resource::<FrustumCulling>();
         app.add_systems(Startup, spawn_render_globals);
+        app.add_systems(
+            Update,
+            (toggle_frustum_culling_system, update_frustum_culling_system).chain(),
+        );
+    }
+}
+
+/// Debug switch for frustum culling of the building meshes
+#[derive(Resource, Debug)]
+pub struct FrustumCulling {
+    pub enabled: bool,
+}
+
+impl Default for FrustumCulling {
+    fn default() -> Self {
+        Self { enabled: true }
     }
 }
 
@@ -19,3 +42,117 @@ fn spawn_render_globals(mut commands: Commands) {
         brightness: 0.9,
     });
 }
+
+fn toggle_frustum_culling_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut culling: ResMut<FrustumCulling>,
+) {
+    if keyboard_input.just_pressed(TOGGLE_CULLING_KEY) {
+        culling.enabled = !culling.enabled;
+        info!("frustum culling enabled = {}", culling.enabled);
+    }
+}
+
+/// Adds or removes `NoFrustumCulling` on the meshes of the building scenes when culling is
+/// toggled, and on newly spawned building meshes while culling is disabled
+fn update_frustum_culling_system(
+    mut commands: Commands,
+    culling: Res<FrustumCulling>,
+    scenes: Query<Entity, With<BuildingScene>>,
+    children: Query<&Children>,
+    parents: Query<&Parent>,
+    meshes: Query<(), With<Handle<Mesh>>>,
+    added: Query<Entity, Added<Handle<Mesh>>>,
+) {
+    if culling.is_changed() {
+        let building_meshes = scenes
+            .iter()
+            .flat_map(|scene| children.iter_descendants(scene))
+            .filter(|entity| meshes.contains(*entity));
+        for entity in building_meshes {
+            if culling.enabled {
+                commands.entity(entity).remove::<NoFrustumCulling>();
+            } else {
+                commands.entity(entity).insert(NoFrustumCulling);
+            }
+        }
+    } else if !culling.enabled {
+        for entity in added.iter() {
+            if parents
+                .iter_ancestors(entity)
+                .any(|ancestor| scenes.contains(ancestor))
+            {
+                commands.entity(entity).insert(NoFrustumCulling);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::building::BuildingSceneDirection;
+
+    fn culling_app() -> App {
+        let mut app = App::new();
+        app.insert_resource(Input::<KeyCode>::default());
+        app.init_resource::<FrustumCulling>();
+        app.add_systems(
+            Update,
+            (toggle_frustum_culling_system, update_frustum_culling_system).chain(),
+        );
+        app
+    }
+
+    fn spawn_mesh(app: &mut App, parent: Option<Entity>) -> Entity {
+        let mut mesh = app.world.spawn(Handle::<Mesh>::default());
+        if let Some(parent) = parent {
+            mesh.set_parent(parent);
+        }
+        mesh.id()
+    }
+
+    fn toggle(app: &mut App) {
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(TOGGLE_CULLING_KEY);
+        app.update();
+        app.world.resource_mut::<Input<KeyCode>>().reset_all();
+    }
+
+    fn culled(app: &App, entity: Entity) -> bool {
+        !app.world.entity(entity).contains::<NoFrustumCulling>()
+    }
+
+    #[test]
+    fn toggle_only_affects_building_meshes() {
+        let mut app = culling_app();
+        let scene = app
+            .world
+            .spawn(BuildingScene {
+                direction: BuildingSceneDirection::LeftRight,
+                width: 10.0,
+                offset: 0.0,
+            })
+            .id();
+        let node = app.world.spawn_empty().set_parent(scene).id();
+        let building_mesh = spawn_mesh(&mut app, Some(node));
+        let other_mesh = spawn_mesh(&mut app, None);
+        app.update();
+        assert!(culled(&app, building_mesh) && culled(&app, other_mesh));
+
+        toggle(&mut app);
+        assert!(!culled(&app, building_mesh));
+        assert!(culled(&app, other_mesh));
+
+        // meshes of scenes spawned while culling is disabled
+        let spawned_mesh = spawn_mesh(&mut app, Some(node));
+        let spawned_other = spawn_mesh(&mut app, None);
+        app.update();
+        assert!(!culled(&app, spawned_mesh));
+        assert!(culled(&app, spawned_other));
+
+        toggle(&mut app);
+        assert!(culled(&app, building_mesh) && culled(&app, spawned_mesh));
+    }
+}
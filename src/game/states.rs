@@ -11,10 +11,14 @@ pub enum GameState {
     AssetsLoading,
     /// All Assets are loaded
     AssetsLoaded,
+    /// Assets failed to load, the failures are tracked by the AssetLoader
+    LoadFailed,
     /// Game is initializing
     GameLoading,
     /// Game is running
     GameRunning,
+    /// The level objective is over, completed or out of time
+    LevelComplete,
 }
 
 pub fn finished_init_system(In(_): In<()>, mut state: ResMut<NextState<GameState>>) {
@@ -34,19 +38,50 @@ pub fn finished_loading_system(
         }
         Err(error) => {
             error!("Error loading assets: {}", error);
+            state.set(GameState::LoadFailed);
         }
         _ => {}
     }
 }
 
-/// Transition system for AssetsLoaded to GameLoading
+/// Transition system for AssetsLoaded to GameLoading, unless preparing the assets failed
 pub fn finished_loaded_system(mut state: ResMut<NextState<GameState>>) {
+    if state.0 == Some(GameState::LoadFailed) {
+        return;
+    }
     info!("finished loaded system -> GameLoading");
     state.set(GameState::GameLoading);
 }
 
-/// Transition system for GameLoading to GameRunning
+/// Transition system for LoadFailed back to AssetsLoading
+pub fn retry_loading_system(In(retry): In<bool>, mut state: ResMut<NextState<GameState>>) {
+    if retry {
+        info!("retry loading system -> AssetsLoading");
+        state.set(GameState::AssetsLoading);
+    }
+}
+
+/// Transition system for GameLoading to GameRunning, unless preparing the game failed
 pub fn finished_game_loading_system(mut state: ResMut<NextState<GameState>>) {
+    if state.0 == Some(GameState::LoadFailed) {
+        return;
+    }
     info!("finished loaded system -> GameRunning");
     state.set(GameState::GameRunning);
 }
+
+/// Transition system for GameRunning to LevelComplete
+pub fn finished_level_system(In(finished): In<bool>, mut state: ResMut<NextState<GameState>>) {
+    if finished {
+        info!("finished level system -> LevelComplete");
+        state.set(GameState::LevelComplete);
+    }
+}
+
+/// Transition system for LevelComplete back to GameRunning
+pub fn replay_level_system(In(replay): In<bool>, mut state: ResMut<NextState<GameState>>) {
+    if replay {
+        info!("replay level system -> GameRunning");
+        state.set(GameState::GameRunning);
+    }
+}
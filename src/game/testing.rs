@@ -0,0 +1,108 @@
+//! Fixtures for headless `App` tests of the game systems.
+
+use std::time::Duration;
+
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+
+use crate::common::graph::{TrackGraph, TrackPoint};
+use crate::common::track::Track;
+
+use super::assets::{BuildingResource, BuildingSceneMetadata, PaperboxResource};
+use super::building::BuildingLayout;
+use super::paperbox::{spawn_paperbox, Paperbox};
+
+/// Height between two floors of the test tracks
+pub const FLOOR_HEIGHT: f32 = 4.0;
+
+/// Straight tracks along +x from 0 to `length`, one per floor.
+pub fn straight_tracks(floors: usize, length: f32) -> TrackGraph {
+    let tracks = (0..floors)
+        .map(|floor| {
+            let y = floor as f32 * FLOOR_HEIGHT;
+            Track::new(vec![Vec3::new(0.0, y, 0.0), Vec3::new(length, y, 0.0)]).unwrap()
+        })
+        .collect();
+    TrackGraph::new(tracks)
+}
+
+fn scene_metadata() -> BuildingSceneMetadata {
+    BuildingSceneMetadata {
+        scene: Handle::default(),
+        width: 0.0,
+        offset: 0.0,
+        markers: Vec::new(),
+    }
+}
+
+/// A building of straight floor tracks whose scenes are empty.
+pub fn straight_building(floors: usize, length: f32) -> BuildingResource {
+    BuildingResource {
+        lopen: scene_metadata(),
+        ropen: scene_metadata(),
+        lropen: scene_metadata(),
+        tracks: straight_tracks(floors, length),
+        layers: (0..floors).collect(),
+    }
+}
+
+/// An app with a building of straight floor tracks, without any plugins. Time only advances
+/// with [`advance`].
+pub fn building_app(floors: usize, length: f32) -> App {
+    let tracks = straight_tracks(floors, length);
+    let mut app = App::new();
+    app.insert_resource(Time::<()>::default());
+    app.insert_resource(Input::<KeyCode>::default());
+    app.insert_resource(straight_building(floors, length));
+    app.insert_resource(BuildingLayout {
+        end: length,
+        tracks,
+        player_spawn: None,
+        boxes: Vec::new(),
+    });
+    app.insert_resource(PaperboxResource {
+        scene: Handle::default(),
+    });
+    app
+}
+
+/// Advances the time by `seconds` and runs one update.
+pub fn advance(app: &mut App, seconds: f32) {
+    app.world
+        .resource_mut::<Time>()
+        .advance_by(Duration::from_secs_f32(seconds));
+    app.update();
+}
+
+/// Presses the key for the next update only.
+pub fn press(app: &mut App, key: KeyCode) {
+    let mut input = app.world.resource_mut::<Input<KeyCode>>();
+    input.clear();
+    input.press(key);
+}
+
+/// Releases all keys.
+pub fn release_all(app: &mut App) {
+    let mut input = app.world.resource_mut::<Input<KeyCode>>();
+    input.release_all();
+    input.clear();
+}
+
+/// Spawns a paperbox at every point and returns them in the same order.
+pub fn spawn_paperboxes(app: &mut App, points: &[TrackPoint]) -> Vec<Entity> {
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, &app.world);
+    for point in points {
+        spawn_paperbox(
+            &mut commands,
+            app.world.resource(),
+            app.world.resource(),
+            *point,
+        );
+    }
+    queue.apply(&mut app.world);
+    let mut query = app.world.query_filtered::<Entity, With<Paperbox>>();
+    let mut entities: Vec<Entity> = query.iter(&app.world).collect();
+    entities.sort();
+    entities.split_off(entities.len() - points.len())
+}
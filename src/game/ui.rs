@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+
+pub const TEXT_COLOR: &str = "#ffffff";
+
+/// Marks the entities of a screen shown outside of the running game, including its camera
+#[derive(Component)]
+pub struct ScreenEntity;
+
+pub fn color(hex: &str) -> Color {
+    Color::hex(hex).expect("Invalid Color!")
+}
+
+pub fn text(value: impl Into<String>, font_size: f32, hex: &str) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font_size,
+            color: color(hex),
+            ..Default::default()
+        },
+    )
+}
+
+/// Background colors of a button
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ButtonColors {
+    pub normal: &'static str,
+    pub hovered: &'static str,
+}
+
+/// Spawns a button with the label, aligned to the start of the parent node.
+pub fn spawn_button(
+    parent: &mut ChildBuilder,
+    label: impl Into<String>,
+    colors: ButtonColors,
+    marker: impl Component,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(16.0), Val::Px(8.0)),
+                    margin: UiRect::top(Val::Px(16.0)),
+                    align_self: AlignSelf::Start,
+                    ..Default::default()
+                },
+                background_color: color(colors.normal).into(),
+                ..Default::default()
+            },
+            colors,
+            marker,
+        ))
+        .with_children(|button| {
+            button.spawn(text(label, 20.0, TEXT_COLOR));
+        });
+}
+
+/// Highlights the hovered button, returns true if it is pressed.
+pub fn update_button(
+    interaction: &Interaction,
+    background: &mut BackgroundColor,
+    colors: &ButtonColors,
+) -> bool {
+    match interaction {
+        Interaction::Pressed => return true,
+        Interaction::Hovered => *background = color(colors.hovered).into(),
+        Interaction::None => *background = color(colors.normal).into(),
+    }
+    false
+}
+
+/// Spawns a camera and a full screen node centering its children, the game camera is inactive
+/// until the game runs.
+pub fn spawn_screen(commands: &mut Commands, children: impl FnOnce(&mut ChildBuilder)) {
+    commands.spawn((Camera2dBundle::default(), ScreenEntity));
+    spawn_overlay(commands, children);
+}
+
+/// Spawns a full screen node centering its children on top of the running game.
+pub fn spawn_overlay(commands: &mut Commands, children: impl FnOnce(&mut ChildBuilder)) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ScreenEntity,
+        ))
+        .with_children(children);
+}
+
+pub fn despawn_screen_system(mut commands: Commands, query: Query<Entity, With<ScreenEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}